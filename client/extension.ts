@@ -50,9 +50,27 @@ export function activate(ctx: vscode.ExtensionContext): void {
         run,
         debug: run,
     };
+    const config = vscode.workspace.getConfiguration("cstyle");
+    const profiles = config.get<any[]>("profiles", []);
+
+    // serve every language and extension that has a profile, defaulting to cstyle
+    const documentSelector: { scheme: string, language?: string, pattern?: string }[] = [{ scheme: "file", language: "cstyle" }];
+    for (const profile of profiles) {
+        for (const language of profile.languageIds || []) {
+            documentSelector.push({ scheme: "file", language });
+        }
+        for (const ext of profile.extensions || []) {
+            documentSelector.push({ scheme: "file", pattern: `**/*.${ext}` });
+        }
+    }
+
     let clientOptions: LanguageClientOptions = {
-        // Register the server for plain text documents
-        documentSelector: [{ scheme: "file", language: "cstyle" }],
+        documentSelector,
+        initializationOptions: {
+            profiles,
+            langDbPath: config.get<string>("langDbPath") || undefined,
+            includePaths: config.get<string[]>("includePaths", []),
+        },
     };
 
 
@@ -1,31 +1,555 @@
 use super::Backend;
+use crate::lang_types::{
+    find_later_declaration, get_scoped_parse_state, resolve_var, ParseState, Scope,
+};
+use crate::lsp_util::{
+    declared_in, is_declaration_site, is_field, is_unused_exempt, node_start, node_to_utf16_range,
+};
+use crate::prov_signature_help::build_signature;
+use crate::type_inference::{infer_type, is_assignable, is_checkable, InferredType};
 use tower_lsp::lsp_types::*;
+use tree_sitter::Node;
 
-impl Backend {
-    // TODO lang specific - implement a diagnostics provider
-    #[allow(unused_variables)]
-    pub async fn generate_diagnostics(&self, text: &str, uri: Url, version: i32) {
-        let mut items: Vec<Diagnostic> = vec![];
+// first line of the node text, shortened so messages stay readable
+fn snippet(src: &str, node: Node) -> String {
+    let text = node.utf8_text(src.as_bytes()).unwrap_or("");
+    let line = text.lines().next().unwrap_or("").trim();
+    match line.char_indices().nth(20) {
+        Some((idx, _)) => return line[..idx].to_owned() + "...",
+        None => return line.to_owned(),
+    }
+}
+
+fn extract_syntax_errors_recursively(
+    src: &str,
+    node: Node,
+    source: &str,
+    items: &mut Vec<Diagnostic>,
+) {
+    if node.is_missing() {
+        let message = match node.is_named() {
+            true => format!("expected {}", node.kind()),
+            false => format!("expected '{}'", node.kind()),
+        };
+        items.push(Diagnostic {
+            range: node_to_utf16_range(src, node),
+            severity: Some(DiagnosticSeverity::ERROR),
+            message,
+            source: Some(source.to_owned()),
+            ..Default::default()
+        });
+        return;
+    }
 
+    if node.is_error() {
+        let text = snippet(src, node);
+        let message = match text.is_empty() {
+            true => "syntax error".to_owned(),
+            false => format!("syntax error, unexpected '{}'", text),
+        };
         items.push(Diagnostic {
-            range: Range {
-                start: Position {
-                    line: 0 as u32,
-                    character: 0 as u32,
-                },
-                end: Position {
-                    line: 0 as u32,
-                    character: 1 as u32,
-                },
-            },
-            severity: Some(DiagnosticSeverity::WARNING),
-            message: "Diagnostics provider not implemented!".to_owned(),
-            source: Some("C-Style Lang Server".to_owned()), // TODO lang specific - set name here
+            range: node_to_utf16_range(src, node),
+            severity: Some(DiagnosticSeverity::ERROR),
+            message,
+            source: Some(source.to_owned()),
             ..Default::default()
         });
+        return; // errors nested inside are part of the same mistake
+    }
+
+    for child in node.children(&mut node.walk()) {
+        if child.has_error() {
+            extract_syntax_errors_recursively(src, child, source, items);
+        }
+    }
+}
+
+// warns about locals and parameters that are never read, globals are skipped as other files may use them
+fn extract_unused_vars_recursively(
+    scope: &Scope,
+    uri: &Url,
+    params: &Vec<Location>,
+    exempt_params: &Vec<Location>,
+    source: &str,
+    items: &mut Vec<Diagnostic>,
+) {
+    for (_, _, sub_scope) in scope.scopes.iter() {
+        for (name, lv) in sub_scope.vars.iter() {
+            let Some(loc) = &lv.declaration_position else {
+                continue;
+            };
+            if !lv.unused
+                || is_unused_exempt(name)
+                || exempt_params.contains(loc)
+                || !declared_in(&lv.declaration_position, uri)
+            {
+                continue;
+            }
+
+            let message = match params.contains(loc) {
+                true => format!("parameter '{}' is never used", name),
+                false => format!("variable '{}' is never used", name),
+            };
+            items.push(Diagnostic {
+                range: loc.range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                message,
+                source: Some(source.to_owned()),
+                tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+                ..Default::default()
+            });
+        }
+
+        extract_unused_vars_recursively(sub_scope, uri, params, exempt_params, source, items);
+    }
+}
+
+// number of single character edits needed to turn a into b
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev[j] + if ca == *cb { 0 } else { 1 };
+            cur.push(substitute.min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    return prev[b.len()];
+}
+
+// the candidate closest to name, if it is close enough to be a typo
+fn closest_match<'a>(name: &str, candidates: impl Iterator<Item = &'a String>) -> Option<String> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    let mut best: Option<(usize, &String)> = None;
+    for candidate in candidates {
+        let distance = edit_distance(name, candidate);
+        if distance == 0 || distance > max_distance {
+            continue;
+        }
+        if best.is_none_or(|(d, c)| (distance, candidate) < (d, c)) {
+            best = Some((distance, candidate));
+        }
+    }
+    return best.map(|(_, c)| c.to_owned());
+}
+
+// macros tested with #ifdef, defined() or #if don't have to be declared
+fn is_preproc_condition(node: Node) -> bool {
+    let mut child = node;
+    while let Some(parent) = child.parent() {
+        match parent.kind() {
+            "preproc_ifdef" | "preproc_defined" => return true,
+            "preproc_if" | "preproc_elif" if is_field(child, parent, "condition") => return true,
+            _ => (),
+        }
+        child = parent;
+    }
+    return false;
+}
+
+// TODO lang specific - declarations the ParseState doesn't track, which are found by syntax instead
+// (enumerators, macro params, function prototypes, and typedef, union and enum names)
+fn is_untracked_declaration(node: Node) -> bool {
+    if !is_declaration_site(node) {
+        return false;
+    }
+    if node.kind() == "type_identifier" {
+        return true;
+    }
+    return node.parent().is_some_and(|parent| {
+        matches!(
+            parent.kind(),
+            "enumerator" | "preproc_params" | "function_declarator"
+        )
+    });
+}
+
+// every untracked name declared in the document, vars are resolved through scopes instead
+fn collect_declared_names_recursively(src: &str, node: Node, names: &mut Vec<String>) {
+    if (node.kind() == "identifier" || node.kind() == "type_identifier")
+        && is_untracked_declaration(node)
+    {
+        names.push(node.utf8_text(src.as_bytes()).unwrap().to_owned());
+    }
+    for child in node.children(&mut node.walk()) {
+        collect_declared_names_recursively(src, child, names);
+    }
+}
+
+fn is_known_identifier(ps: &ParseState, declared: &[String], name: &str, loc: Position) -> bool {
+    return resolve_var(&ps.global_scope, name, loc).is_some()
+        || ps.functions.contains_key(name)
+        || ps.defines.contains_key(name)
+        || ps.keywords.iter().any(|(_, label)| label == name)
+        || declared.iter().any(|n| n == name);
+}
+
+fn is_known_type(ps: &ParseState, declared: &[String], name: &str) -> bool {
+    return ps.types.contains_key(name)
+        || ps.defines.contains_key(name)
+        || declared.iter().any(|n| n == name);
+}
+
+fn unresolved_message(kind: &str, name: &str, suggestion: Option<String>) -> String {
+    match suggestion {
+        Some(suggestion) => return format!("{} '{}', did you mean '{}'?", kind, name, suggestion),
+        None => return format!("{} '{}'", kind, name),
+    }
+}
+
+// errors on identifiers and type names that don't resolve to anything in scope
+// warns about locals that are used before they are declared
+fn extract_unresolved_names_recursively(
+    ps: &ParseState,
+    node: Node,
+    declared: &Vec<String>,
+    source: &str,
+    items: &mut Vec<Diagnostic>,
+) {
+    if node.is_error() || node.is_missing() {
+        return; // already reported as a syntax error
+    }
+
+    let kind = node.kind();
+    if (kind == "identifier" || kind == "type_identifier")
+        && !is_declaration_site(node)
+        && !is_preproc_condition(node)
+    {
+        let name = node.utf8_text(ps.text.as_bytes()).unwrap();
+        let loc = node_start(&ps.text, node);
+
+        let used_early = kind == "identifier"
+            && resolve_var(&ps.global_scope, name, loc).is_none()
+            && !ps.functions.contains_key(name)
+            && !ps.defines.contains_key(name)
+            && find_later_declaration(&ps.global_scope, name, loc).is_some();
+
+        let message = if used_early {
+            Some(format!("'{}' is used before it is declared", name))
+        } else if kind == "identifier" && !is_known_identifier(ps, declared, name, loc) {
+            let sps = get_scoped_parse_state(ps, loc);
+            let candidates = sps
+                .vars
+                .keys()
+                .chain(ps.functions.keys())
+                .chain(ps.defines.keys())
+                .chain(ps.keywords.iter().map(|(_, label)| label))
+                .chain(declared.iter());
+            Some(unresolved_message(
+                "undeclared identifier",
+                name,
+                closest_match(name, candidates),
+            ))
+        } else if kind == "type_identifier" && !is_known_type(ps, declared, name) {
+            let candidates = ps.types.keys().chain(declared.iter());
+            Some(unresolved_message(
+                "unknown type",
+                name,
+                closest_match(name, candidates),
+            ))
+        } else {
+            None
+        };
+
+        if let Some(message) = message {
+            let severity = match used_early {
+                true => DiagnosticSeverity::WARNING,
+                false => DiagnosticSeverity::ERROR,
+            };
+            items.push(Diagnostic {
+                range: node_to_utf16_range(&ps.text, node),
+                severity: Some(severity),
+                message,
+                source: Some(source.to_owned()),
+                ..Default::default()
+            });
+        }
+    }
+
+    for child in node.children(&mut node.walk()) {
+        extract_unresolved_names_recursively(ps, child, declared, source, items);
+    }
+}
+
+// the identifier a (possibly nested) declarator declares
+fn declarator_identifier(node: Node) -> Option<Node> {
+    let mut node = node;
+    while node.kind() != "identifier" {
+        node = node.child_by_field_name("declarator")?;
+    }
+    return Some(node);
+}
+
+// the name of the function containing node
+fn enclosing_function_name<'a>(src: &'a str, node: Node) -> Option<&'a str> {
+    let mut node = node.parent()?;
+    while node.kind() != "function_definition" {
+        node = node.parent()?;
+    }
+    let ident_node = declarator_identifier(node.child_by_field_name("declarator")?)?;
+    return ident_node.utf8_text(src.as_bytes()).ok();
+}
+
+// Some((found, expected)) if value can't be used as expected
+fn check_type(
+    ps: &ParseState,
+    value: Node,
+    expected: &InferredType,
+) -> Option<(InferredType, InferredType)> {
+    // TODO lang specific - `0` is a null pointer
+    let is_null = value.kind() == "number_literal"
+        && value.utf8_text(ps.text.as_bytes()) == Ok("0")
+        && !expected.type_qualifier_list.is_empty();
+    let found = infer_type(ps, value)?;
+    if is_null
+        || !is_checkable(ps, &found)
+        || !is_checkable(ps, expected)
+        || is_assignable(ps, &found, expected)
+    {
+        return None;
+    }
+    return Some((found, expected.clone()));
+}
+
+// errors on initializers, assignments, returns and call arguments with the wrong type
+fn extract_type_mismatches_recursively(
+    ps: &ParseState,
+    node: Node,
+    source: &str,
+    items: &mut Vec<Diagnostic>,
+) {
+    if node.is_error() || node.is_missing() {
+        return;
+    }
+
+    let src = &ps.text;
+    let mut mismatches = vec![];
+    match node.kind() {
+        "init_declarator" => {
+            let value = node.child_by_field_name("value");
+            let ident_node = node
+                .child_by_field_name("declarator")
+                .and_then(declarator_identifier);
+            if let (Some(value), Some(ident_node)) = (value, ident_node) {
+                let name = ident_node.utf8_text(src.as_bytes()).unwrap();
+                let loc = node_start(src, ident_node);
+                if let Some(lv) = resolve_var(&ps.global_scope, name, loc) {
+                    if let Some((found, expected)) =
+                        check_type(ps, value, &InferredType::from_var(lv))
+                    {
+                        mismatches.push((
+                            value,
+                            format!(
+                                "cannot initialize '{}' of type '{}' with a value of type '{}'",
+                                name, expected, found
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+        "assignment_expression" => {
+            let left = node.child_by_field_name("left");
+            let right = node.child_by_field_name("right");
+            let is_plain = node
+                .child_by_field_name("operator")
+                .is_some_and(|op| op.kind() == "=");
+            if let (Some(left), Some(right), true) = (left, right, is_plain) {
+                if let Some(expected) = infer_type(ps, left) {
+                    if let Some((found, expected)) = check_type(ps, right, &expected) {
+                        mismatches.push((
+                            right,
+                            format!(
+                                "cannot assign a value of type '{}' to '{}'",
+                                found, expected
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+        "return_statement" => {
+            let value = node.named_child(0);
+            let lf = enclosing_function_name(src, node).and_then(|name| ps.functions.get(name));
+            if let (Some(value), Some(lf)) = (value, lf) {
+                let expected = InferredType::parse(&lf.return_type);
+                if let Some((found, expected)) = check_type(ps, value, &expected) {
+                    mismatches.push((
+                        value,
+                        format!(
+                            "cannot return a value of type '{}' from a function returning '{}'",
+                            found, expected
+                        ),
+                    ));
+                }
+            }
+        }
+        "call_expression" => {
+            let lf = node
+                .child_by_field_name("function")
+                .and_then(|f| ps.functions.get(f.utf8_text(src.as_bytes()).unwrap()));
+            let arguments = node.child_by_field_name("arguments");
+            if let (Some(lf), Some(arguments)) = (lf, arguments) {
+                let mut cursor = arguments.walk();
+                let args = arguments
+                    .named_children(&mut cursor)
+                    .filter(|arg| !arg.is_extra());
+                for (arg, (param_name, param)) in args.zip(lf.params.iter()) {
+                    if let Some((found, expected)) =
+                        check_type(ps, arg, &InferredType::from_var(param))
+                    {
+                        mismatches.push((
+                            arg,
+                            format!(
+                                "cannot pass a value of type '{}' to parameter '{}' of type '{}'",
+                                found, param_name, expected
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+        _ => (),
+    }
+
+    for (value, message) in mismatches {
+        items.push(Diagnostic {
+            range: node_to_utf16_range(src, value),
+            severity: Some(DiagnosticSeverity::ERROR),
+            message,
+            source: Some(source.to_owned()),
+            ..Default::default()
+        });
+    }
+
+    for child in node.children(&mut node.walk()) {
+        extract_type_mismatches_recursively(ps, child, source, items);
+    }
+}
+
+// errors on calls with too few or too many arguments
+fn extract_arity_errors_recursively(
+    ps: &ParseState,
+    node: Node,
+    source: &str,
+    items: &mut Vec<Diagnostic>,
+) {
+    if node.is_error() || node.is_missing() {
+        return;
+    }
+
+    if node.kind() == "call_expression" {
+        let function = node.child_by_field_name("function");
+        let arguments = node.child_by_field_name("arguments");
+        if let (Some(function), Some(arguments)) = (function, arguments) {
+            let name = function.utf8_text(ps.text.as_bytes()).unwrap();
+            if let Some(lf) = ps.functions.get(name) {
+                let mut cursor = arguments.walk();
+                let args: Vec<Node> = arguments
+                    .named_children(&mut cursor)
+                    .filter(|arg| !arg.is_extra() && !arg.is_error())
+                    .collect();
+
+                let mut error = None;
+                if args.len() < lf.params.len() {
+                    error = Some(("too few", node_to_utf16_range(&ps.text, arguments)));
+                } else if args.len() > lf.params.len() && !lf.variadic {
+                    let extra = Range {
+                        start: node_start(&ps.text, args[lf.params.len()]),
+                        end: node_to_utf16_range(&ps.text, args[args.len() - 1]).end,
+                    };
+                    error = Some(("too many", extra));
+                }
+
+                if let Some((amount, range)) = error {
+                    items.push(Diagnostic {
+                        range,
+                        severity: Some(DiagnosticSeverity::ERROR),
+                        message: format!(
+                            "{} arguments to '{}', expected '{}'",
+                            amount,
+                            name,
+                            build_signature(name, lf).label
+                        ),
+                        source: Some(source.to_owned()),
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+    }
+
+    for child in node.children(&mut node.walk()) {
+        extract_arity_errors_recursively(ps, child, source, items);
+    }
+}
+
+// TODO lang specific - add language specific diagnostics here
+pub fn get_diagnostics(parse_state: &ParseState, uri: &Url, source: &str) -> Vec<Diagnostic> {
+    let mut items = vec![];
+    if let Some(tree) = &parse_state.tree {
+        extract_syntax_errors_recursively(&parse_state.text, tree.root_node(), source, &mut items);
+
+        let mut declared = vec![];
+        collect_declared_names_recursively(&parse_state.text, tree.root_node(), &mut declared);
+        extract_unresolved_names_recursively(
+            parse_state,
+            tree.root_node(),
+            &declared,
+            source,
+            &mut items,
+        );
+        extract_type_mismatches_recursively(parse_state, tree.root_node(), source, &mut items);
+        extract_arity_errors_recursively(parse_state, tree.root_node(), source, &mut items);
+    }
+
+    // parameters of main are required by the signature, even if unused
+    let mut params = vec![];
+    let mut exempt_params = vec![];
+    for (func_name, lf) in parse_state.functions.iter() {
+        for (_, lv) in lf.params.iter() {
+            if let Some(loc) = &lv.declaration_position {
+                params.push(loc.clone());
+                if func_name == "main" {
+                    exempt_params.push(loc.clone());
+                }
+            }
+        }
+    }
+    extract_unused_vars_recursively(
+        &parse_state.global_scope,
+        uri,
+        &params,
+        &exempt_params,
+        source,
+        &mut items,
+    );
+
+    // scopes are hash maps, sort so the output is deterministic
+    items.sort_by_key(|d| (d.range.start.line, d.range.start.character));
+    return items;
+}
+
+impl Backend {
+    // publishes diagnostics for an open document, sourced with the document's profile name
+    pub async fn generate_diagnostics(&self, uri: Url, version: Option<i32>) {
+        let source = {
+            let profiles = self.profiles.read().await;
+            let profile = self.document_profile(&uri, &profiles).await;
+            profiles[profile].config.name.clone()
+        };
+
+        let items = match self.documents.read().await.get(&uri) {
+            Some(parse_state) => get_diagnostics(parse_state, &uri, &source),
+            None => vec![],
+        };
+
+        self.client.publish_diagnostics(uri, items, version).await;
+    }
 
-        self.client
-            .publish_diagnostics(uri, items, Some(version))
-            .await;
+    pub async fn clear_diagnostics(&self, uri: Url) {
+        self.client.publish_diagnostics(uri, vec![], None).await;
     }
 }
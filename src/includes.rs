@@ -0,0 +1,122 @@
+use crate::lang_types::*;
+use crate::workspace_index::{IndexedFile, WorkspaceIndex};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tower_lsp::lsp_types::*;
+
+// quoted includes are searched for next to the including file first, then in the include paths
+// system includes are only searched for in the include paths
+// indexed and cached files are found even if they aren't on disk (yet)
+pub fn resolve_include(
+    include: &LangInclude,
+    including_file: &Path,
+    include_paths: &[PathBuf],
+    index: &WorkspaceIndex,
+) -> Option<PathBuf> {
+    let mut candidates = vec![];
+    if !include.system {
+        if let Some(dir) = including_file.parent() {
+            candidates.push(dir.join(&include.path));
+        }
+    }
+    for include_path in include_paths {
+        candidates.push(include_path.join(&include.path));
+    }
+
+    return candidates
+        .into_iter()
+        .find(|candidate| index.contains(candidate) || candidate.is_file());
+}
+
+// merges objects declared in `included` (and not already known) into the including parse state
+fn merge_included(ps: &mut ParseState, included: &IndexedFile) {
+    for (name, lt) in included.types.iter() {
+        if !ps.types.contains_key(name) {
+            ps.types.insert(name.to_owned(), lt.clone());
+        }
+    }
+    for (name, lf) in included.functions.iter() {
+        if !ps.functions.contains_key(name) {
+            ps.functions.insert(name.to_owned(), lf.clone());
+        }
+    }
+    for (name, ld) in included.defines.iter() {
+        if !ps.defines.contains_key(name) {
+            ps.defines.insert(name.to_owned(), ld.clone());
+        }
+    }
+    for (name, lv) in included.globals.iter() {
+        if !ps.global_scope.vars.contains_key(name) {
+            ps.global_scope.vars.insert(name.to_owned(), lv.clone());
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)] // the index doubles as the cache of parsed includes
+fn merge_includes_recursive(
+    ps: &mut ParseState,
+    includes: &[LangInclude],
+    including_file: &Path,
+    index: &mut WorkspaceIndex,
+    profile: usize,
+    lang_db: &LangDB,
+    include_paths: &[PathBuf],
+    visited: &mut HashSet<PathBuf>,
+) {
+    for include in includes {
+        let Some(path) = resolve_include(include, including_file, include_paths, index) else {
+            continue;
+        };
+        let path = path.canonicalize().unwrap_or(path);
+        if !visited.insert(path.clone()) {
+            continue; // include cycle, or already merged through another include
+        }
+
+        let Some(included) = index.included_file(&path, profile, lang_db) else {
+            continue;
+        };
+        merge_included(ps, included);
+        let nested = included.includes.clone();
+        merge_includes_recursive(
+            ps,
+            &nested,
+            &path,
+            index,
+            profile,
+            lang_db,
+            include_paths,
+            visited,
+        );
+    }
+}
+
+// follows the document's `#include` directives (recursively) and merges in the included objects
+// objects declared in the document itself take priority
+// included files are taken from the workspace index, only files that aren't indexed or cached are read from disk
+pub fn merge_includes(
+    ps: &mut ParseState,
+    uri: &Url,
+    index: &mut WorkspaceIndex,
+    profile: usize,
+    lang_db: &LangDB,
+    include_paths: &[PathBuf],
+) {
+    let Ok(file_path) = uri.to_file_path() else {
+        return;
+    };
+
+    let mut visited = HashSet::new();
+    visited.insert(file_path.canonicalize().unwrap_or(file_path.clone()));
+
+    let includes = ps.includes.clone();
+    merge_includes_recursive(
+        ps,
+        &includes,
+        &file_path,
+        index,
+        profile,
+        lang_db,
+        include_paths,
+        &mut visited,
+    );
+}
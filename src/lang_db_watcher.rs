@@ -0,0 +1,62 @@
+use super::Backend;
+use log::{debug, error};
+use tower_lsp::lsp_types::*;
+
+const WATCHER_ID: &str = "lang-db-watcher";
+
+impl Backend {
+    // asks the client to send workspace/didChangeWatchedFiles when a profile's LangDB file changes
+    pub async fn register_lang_db_watcher(&self) {
+        let mut watchers = vec![];
+        for profile in self.profiles.read().await.iter() {
+            let Some(path) = &profile.config.lang_db_path else {
+                continue;
+            };
+            let (Some(dir), Some(file_name)) = (path.parent(), path.file_name()) else {
+                continue;
+            };
+            let Ok(base_uri) = Url::from_directory_path(dir) else {
+                continue;
+            };
+
+            watchers.push(FileSystemWatcher {
+                glob_pattern: GlobPattern::Relative(RelativePattern {
+                    base_uri: OneOf::Right(base_uri),
+                    pattern: file_name.to_string_lossy().to_string(),
+                }),
+                kind: None, // create, change and delete
+            });
+        }
+        if watchers.is_empty() {
+            return;
+        }
+
+        let options = DidChangeWatchedFilesRegistrationOptions { watchers };
+
+        let registration = Registration {
+            id: WATCHER_ID.to_owned(),
+            method: "workspace/didChangeWatchedFiles".to_owned(),
+            register_options: Some(serde_json::to_value(options).unwrap()),
+        };
+        if let Err(e) = self.client.register_capability(vec![registration]).await {
+            error!("couldn't watch the LangDB file: {}", e);
+        }
+    }
+
+    // reloads a profile's LangDB, re-parses its open documents against it and refreshes the client
+    // a LangDB that fails to load is reported and the previous one is kept
+    pub async fn reload_lang_db(&self, profile: usize) {
+        let config = self.profiles.read().await[profile].config.clone();
+        let Some(lang_db) = self.load_lang_db(&config).await else {
+            return;
+        };
+        self.profiles.write().await[profile].lang_db = lang_db;
+        debug!("{} LangDB reloaded", config.name);
+
+        self.remerge_documents(&[profile], None).await;
+
+        // refresh requests fail if the client doesn't support them, which is fine
+        let _ = self.client.semantic_tokens_refresh().await;
+        let _ = self.client.inlay_hint_refresh().await;
+    }
+}
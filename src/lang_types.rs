@@ -1,5 +1,8 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
 use tower_lsp::lsp_types::{CompletionItemKind, Location, Position};
 use tree_sitter::Tree;
 
@@ -13,9 +16,10 @@ pub struct LangVar {
     // used for providing completions
     pub primary_type: String,
 
-    // types qualifiers applied to this var
-    // for example, `const int my2dArr[5][5] -> ['const', '[]', '[]']`
-    // used for providing completions
+    // types qualifiers applied to this var, storage classes and qualifiers of the declaration come first
+    // then declarator qualifiers (`*`, `[]`, `()`), outermost first so the last is removed by indexing
+    // for example, `static const int *my2dArr[5][5] -> ['static', 'const', '*', '[]', '[]']`
+    // used for providing completions, hovers and type inference
     #[serde(default)] // default type_qualifier_list to [] if not provided in lang db
     pub type_qualifier_list: Vec<String>,
 
@@ -29,6 +33,43 @@ pub struct LangVar {
     pub unused: bool,
 }
 
+impl LangVar {
+    // C style declaration such as `static const char *name[]`, name can be empty for just the type
+    pub fn declaration_label(&self, name: &str) -> String {
+        let first_indirection = self
+            .type_qualifier_list
+            .iter()
+            .position(|q| q == "*" || q == "[]" || q == "()")
+            .unwrap_or(self.type_qualifier_list.len());
+        let (leading, declarator_qualifiers) = self.type_qualifier_list.split_at(first_indirection);
+
+        // the declarator is built from the name outwards, the innermost qualifier is last
+        let mut declarator = name.to_owned();
+        for qualifier in declarator_qualifiers.iter().rev() {
+            match qualifier.as_str() {
+                "*" => declarator = "*".to_owned() + &declarator,
+                "[]" | "()" => {
+                    if declarator.starts_with('*') {
+                        declarator = "(".to_owned() + &declarator + ")";
+                    }
+                    declarator += qualifier;
+                }
+                _ => declarator = qualifier.to_owned() + " " + &declarator,
+            }
+        }
+
+        let mut label = leading.join(" ");
+        if !label.is_empty() {
+            label += " ";
+        }
+        label += &self.primary_type;
+        if !declarator.is_empty() && !declarator.starts_with('[') {
+            label += " ";
+        }
+        return label + declarator.trim_end();
+    }
+}
+
 fn serde_default_true() -> bool {
     true
 }
@@ -65,6 +106,14 @@ pub struct LangFunc {
     // function return type
     pub return_type: String,
 
+    // accepts any number of extra arguments after params (such as `printf(fmt, ...)`)
+    #[serde(default)] // default variadic to false if not provided in lang db
+    pub variadic: bool,
+
+    // marks uses of the function with the deprecated semantic token modifier
+    #[serde(default)] // default deprecated to false if not provided in lang db
+    pub deprecated: bool,
+
     // declaration location within any document
     // used for providing goto definition
     pub declaration_position: Option<Location>,
@@ -88,10 +137,42 @@ pub struct LangDefine {
     pub declaration_position: Option<Location>,
 }
 
+// An `#include` directive
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LangInclude {
+    // the path as written, without quotes or angle brackets
+    pub path: String,
+
+    // true for `#include <file>`, which is only searched for in the include paths
+    pub system: bool,
+
+    // location of the path within the document
+    pub declaration_position: Location,
+}
+
+// Rules used when inferring and comparing expression types
+// every rule is optional, expressions that rely on a missing rule are not type checked
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct TypeRules {
+    // maps a type to the types it is implicitly converted to (such as `int` -> `float`)
+    // also decides the result of arithmetic on mixed types
+    #[serde(default)]
+    pub implicit_conversions: HashMap<String, Vec<String>>,
+
+    // result of comparison and logical operators, and of `true` / `false`
+    pub boolean_type: Option<String>,
+
+    // types of literals (such as `4`, `4.0`, `'c'` and `"str"`), `[]` and `*` suffixes are allowed
+    pub int_literal_type: Option<String>,
+    pub float_literal_type: Option<String>,
+    pub char_literal_type: Option<String>,
+    pub string_literal_type: Option<String>,
+}
+
 //// LangDB
 
 // Holds information about language syntax and builtins, does not depend on document contents
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct LangDB {
     // Builtin variables
     pub builtin_vars: HashMap<String, LangVar>,
@@ -113,6 +194,20 @@ pub struct LangDB {
 
     // Preprocessor macros (#define) - merged into ParseState keywords
     pub preprocessor: Vec<String>,
+
+    // Type compatibility rules - copied into ParseState
+    #[serde(default)]
+    pub type_rules: TypeRules,
+}
+
+impl LangDB {
+    // loads a LangDB json file, errors are human readable so they can be shown to the user
+    pub fn load(path: &Path) -> Result<LangDB, String> {
+        let file = File::open(path)
+            .map_err(|e| format!("Couldn't open LangDB {}: {}", path.display(), e))?;
+        return serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| format!("Couldn't parse LangDB {}: {}", path.display(), e));
+    }
 }
 
 //// Parse Outputs
@@ -125,8 +220,8 @@ pub struct Scope {
     // Maps var name to LangVar object
     pub vars: HashMap<String, LangVar>,
 
-    // Nested scopes (start line, end line, scope)
-    pub scopes: Vec<(u32, u32, Scope)>,
+    // Nested scopes (start, end, scope), the end is exclusive
+    pub scopes: Vec<(Position, Position, Scope)>,
 }
 
 // Holds information about the document state after parsing, based on the document text and the LangDB
@@ -153,11 +248,20 @@ pub struct ParseState {
     // All scope specific objects are stored in nested scopes accessible from the global scope
     // builtin vars are placed in the global scope
     pub global_scope: Scope,
+
+    // Every call expression in this document (function name, location)
+    // used for resolving references to functions declared in other files
+    pub calls: Vec<(String, Location)>,
+
+    // `#include` directives in this document, in order
+    pub includes: Vec<LangInclude>,
+
+    // Type compatibility rules from the LangDB
+    pub type_rules: TypeRules,
 }
 
 // Holds information about the document after resolving the active scope
 #[derive(Debug)]
-#[allow(dead_code)] // the SPS tree is not currently accessed
 pub struct ScopedParseState<'src> {
     // The raw text of the source file
     pub text: &'src String,
@@ -183,22 +287,116 @@ pub struct ScopedParseState<'src> {
 
 //// SPS Functions
 
+pub fn scope_contains(scope: &(Position, Position, Scope), loc: Position) -> bool {
+    return scope.0 <= loc && loc < scope.1;
+}
+
+// locals are only visible after their declaration
+// globals are always visible, as they can be declared in other files and includes
+fn is_visible(lv: &LangVar, loc: Position, local: bool) -> bool {
+    return !local
+        || lv
+            .declaration_position
+            .as_ref()
+            .is_none_or(|declaration| declaration.range.start <= loc);
+}
+
 fn add_scoped_vars_recursive(
     active_scope: &Scope,
     loc: Position,
+    local: bool,
     vars: &mut HashMap<String, LangVar>,
 ) {
-    vars.extend(active_scope.vars.clone());
+    for (name, lv) in active_scope.vars.iter() {
+        if is_visible(lv, loc, local) {
+            vars.insert(name.to_owned(), lv.clone());
+        }
+    }
     for scope in active_scope.scopes.iter() {
-        if loc.line >= scope.0 && loc.line <= scope.1 {
-            add_scoped_vars_recursive(&scope.2, loc, vars);
+        if scope_contains(scope, loc) {
+            add_scoped_vars_recursive(&scope.2, loc, true, vars);
+        }
+    }
+}
+
+// finds the innermost scope that contains loc and declares name, as a path of indexes into nested scopes
+fn find_var_scope_path(
+    active_scope: &Scope,
+    name: &str,
+    loc: Position,
+    local: bool,
+    path: &mut Vec<usize>,
+) -> bool {
+    for (idx, scope) in active_scope.scopes.iter().enumerate() {
+        if scope_contains(scope, loc) {
+            path.push(idx);
+            if find_var_scope_path(&scope.2, name, loc, true, path) {
+                return true;
+            }
+            path.pop();
         }
     }
+    return active_scope
+        .vars
+        .get(name)
+        .is_some_and(|lv| is_visible(lv, loc, local));
+}
+
+// a local declared after loc in a scope containing loc, for names that are used before they are declared
+pub fn find_later_declaration<'a>(
+    active_scope: &'a Scope,
+    name: &str,
+    loc: Position,
+) -> Option<&'a LangVar> {
+    for scope in active_scope.scopes.iter() {
+        if scope_contains(scope, loc) {
+            if let Some(lv) = find_later_declaration(&scope.2, name, loc) {
+                return Some(lv);
+            }
+            if let Some(lv) = scope.2.vars.get(name) {
+                if !is_visible(lv, loc, true) {
+                    return Some(lv);
+                }
+            }
+        }
+    }
+    return None;
+}
+
+// resolves name at loc to the LangVar it refers to, following the same shadowing and declaration order rules as the SPS
+pub fn resolve_var<'a>(global_scope: &'a Scope, name: &str, loc: Position) -> Option<&'a LangVar> {
+    let mut path = vec![];
+    if !find_var_scope_path(global_scope, name, loc, false, &mut path) {
+        return None;
+    }
+
+    let mut scope = global_scope;
+    for idx in path {
+        scope = &scope.scopes[idx].2;
+    }
+    return scope.vars.get(name);
+}
+
+pub fn resolve_var_mut<'a>(
+    global_scope: &'a mut Scope,
+    name: &str,
+    loc: Position,
+) -> Option<&'a mut LangVar> {
+    let mut path = vec![];
+    if !find_var_scope_path(global_scope, name, loc, false, &mut path) {
+        return None;
+    }
+
+    let mut scope = global_scope;
+    for idx in path {
+        scope = &mut scope.scopes[idx].2;
+    }
+    return scope.vars.get_mut(name);
 }
 
-pub fn get_scoped_parse_state(ps: &ParseState, loc: Position) -> ScopedParseState {
+pub fn get_scoped_parse_state(ps: &ParseState, loc: Position) -> ScopedParseState<'_> {
     let mut vars = HashMap::new();
-    add_scoped_vars_recursive(&ps.global_scope, loc, &mut vars);
+    add_scoped_vars_recursive(&ps.global_scope, loc, false, &mut vars);
 
     let sps = ScopedParseState {
         text: &ps.text,
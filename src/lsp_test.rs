@@ -1,16 +1,25 @@
 #[cfg(test)]
 mod tests {
+    use crate::diagnostics;
+    use crate::includes;
     use crate::lang_types::*;
     use crate::lsp_util;
     use crate::parser;
+    use crate::profiles;
     use crate::prov_completions;
+    use crate::prov_document_symbol;
     use crate::prov_folding;
+    use crate::prov_goto;
+    use crate::prov_highlight;
     use crate::prov_hover;
+    use crate::prov_rename;
     use crate::prov_semantic_tokens;
+    use crate::prov_signature_help;
+    use crate::prov_workspace_symbol;
+    use crate::symbols;
+    use crate::workspace_index;
     use std::collections::HashMap;
     use std::env;
-    use std::fs::File;
-    use std::io::BufReader;
     use tower_lsp::lsp_types::*;
 
     fn location_of(src: &str, item: &str, uri: &Url) -> Location {
@@ -43,6 +52,7 @@ mod tests {
             constants: vec![],
             preprocessor: vec![],
             builtin_vars: HashMap::new(),
+            type_rules: TypeRules::default(),
         };
 
         let sample_code = r#"
@@ -127,20 +137,28 @@ mod tests {
             LangFunc {
                 params: vec![param_var.clone()],
                 return_type: "void".to_owned(),
+                variadic: false,
+                deprecated: false,
                 declaration_position: Some(location_of(sample_code, "main", &sample_uri)),
                 references: vec![],
                 desc: "".to_owned(),
             },
         );
 
-        let main_func_start = 11;
-        let main_func_end = 16;
+        let main_func_start = Position {
+            line: 11,
+            character: 8,
+        };
+        let main_func_end = Position {
+            line: 16,
+            character: 9,
+        };
         let expected_global_scope = Scope {
             vars: HashMap::from([(
                 "global_var".to_string(),
                 LangVar {
                     primary_type: "double".to_string(),
-                    type_qualifier_list: vec![],
+                    type_qualifier_list: vec!["const".to_string()],
                     declaration_position: Some(location_of(sample_code, "global_var", &sample_uri)),
                     unused: true,
                 },
@@ -154,7 +172,7 @@ mod tests {
                             "init_var".to_string(),
                             LangVar {
                                 primary_type: "float".to_string(),
-                                type_qualifier_list: vec![],
+                                type_qualifier_list: vec!["const".to_string()],
                                 declaration_position: Some(location_of(
                                     sample_code,
                                     "init_var",
@@ -225,6 +243,317 @@ mod tests {
         assert_eq!(result.types, expected_types);
     }
 
+    #[test]
+    fn validate_incremental_sync() {
+        let (empty_lang_db, sample_code, sample_uri) = shared_sample_code();
+        let initial = parser::parse(sample_code.to_owned(), &sample_uri, &empty_lang_db);
+
+        let range = |sl, sc, el, ec| {
+            Some(Range {
+                start: Position {
+                    line: sl,
+                    character: sc,
+                },
+                end: Position {
+                    line: el,
+                    character: ec,
+                },
+            })
+        };
+        let changes = vec![
+            // rename prim_var -> other_var
+            TextDocumentContentChangeEvent {
+                range: range(13, 17, 13, 21),
+                range_length: None,
+                text: "other".to_owned(),
+            },
+            // insert a new local before the closing brace
+            TextDocumentContentChangeEvent {
+                range: range(16, 8, 16, 8),
+                range_length: None,
+                text: "    int added_var;\n        ".to_owned(),
+            },
+            // delete the define line
+            TextDocumentContentChangeEvent {
+                range: range(2, 0, 3, 0),
+                range_length: None,
+                text: "".to_owned(),
+            },
+        ];
+
+        let result = parser::parse_incremental(initial, &changes, &sample_uri, &empty_lang_db);
+        let expected_text = sample_code
+            .replace("prim_var", "other_var")
+            .replace("        }\n", "            int added_var;\n        }\n")
+            .replace("        #define myRep 10\n", "");
+        let expected = parser::parse(expected_text.clone(), &sample_uri, &empty_lang_db);
+
+        assert_eq!(result.text, expected_text);
+        assert_eq!(
+            result.tree.as_ref().unwrap().root_node().to_sexp(),
+            expected.tree.as_ref().unwrap().root_node().to_sexp()
+        );
+        assert_eq!(result.global_scope, expected.global_scope);
+        assert_eq!(result.defines, expected.defines);
+        assert_eq!(result.functions, expected.functions);
+    }
+
+    #[test]
+    fn validate_lang_db_reparse() {
+        let (empty_lang_db, sample_code, sample_uri) = shared_sample_code();
+        let initial = parser::parse(sample_code.to_owned(), &sample_uri, &empty_lang_db);
+        let initial_sexp = initial.tree.as_ref().unwrap().root_node().to_sexp();
+
+        let mut new_lang_db = empty_lang_db.clone();
+        new_lang_db.defines.insert(
+            "NEW_DEFINE".to_owned(),
+            LangDefine {
+                insert_text: "1".to_owned(),
+                declaration_position: None,
+            },
+        );
+
+        // reparsing with no changes keeps the document but picks up the new builtins
+        let result = parser::parse_incremental(initial, &[], &sample_uri, &new_lang_db);
+        assert_eq!(result.text, sample_code);
+        assert_eq!(result.tree.unwrap().root_node().to_sexp(), initial_sexp);
+        assert!(result.defines.contains_key("NEW_DEFINE"));
+        assert!(result.defines.contains_key("myRep"));
+    }
+
+    #[test]
+    fn validate_workspace_index() {
+        let (empty_lang_db, _, _) = shared_sample_code();
+        let lib_code = "struct Shape {\n    int sides;\n};\n#define SIDES 4\nint area(Shape s) {\n    return 0;\n}\n";
+        let main_code = "void main() {\n    Shape sq;\n    area(sq);\n}\n";
+        let lib_uri = Url::parse("file:///ws/lib.cstyle").unwrap();
+        let main_uri = Url::parse("file:///ws/main.cstyle").unwrap();
+
+        let mut index = workspace_index::WorkspaceIndex::default();
+        let mut lib_state = parser::parse(lib_code.to_owned(), &lib_uri, &empty_lang_db);
+        let mut main_state = parser::parse(main_code.to_owned(), &main_uri, &empty_lang_db);
+        assert!(index.update(&lib_uri, &lib_state, 0));
+        assert!(index.update(&main_uri, &main_state, 0));
+
+        // only changes to the objects a file declares make other files outdated
+        let edited_body = lib_code.replace("return 0;", "return 1;");
+        let edited_state = parser::parse(edited_body, &lib_uri, &empty_lang_db);
+        assert!(!index.update(&lib_uri, &edited_state, 0));
+        let renamed = lib_code.replace("SIDES", "CORNERS");
+        let renamed_state = parser::parse(renamed, &lib_uri, &empty_lang_db);
+        assert!(index.update(&lib_uri, &renamed_state, 0));
+        let retyped = lib_code.replace("int area", "float area");
+        let retyped_state = parser::parse(retyped, &lib_uri, &empty_lang_db);
+        assert!(index.update(&lib_uri, &retyped_state, 0));
+        assert!(index.update(&lib_uri, &lib_state, 0));
+
+        // declarations that only moved don't count, but merged documents have stale locations
+        index.mark_merged(&main_uri);
+        let moved_state = parser::parse("\n".to_owned() + lib_code, &lib_uri, &empty_lang_db);
+        assert!(!index.update(&lib_uri, &moved_state, 0));
+        assert!(index.is_outdated(&main_uri));
+        assert!(!index.update(&lib_uri, &lib_state, 0));
+        index.mark_merged(&main_uri);
+        assert!(!index.is_outdated(&main_uri));
+
+        index.merge_into(&mut lib_state, &lib_uri, 0);
+        index.merge_into(&mut main_state, &main_uri, 0);
+
+        // objects from lib are visible in main, and point back into lib
+        assert_eq!(
+            main_state.types["Shape"].declaration_position,
+            Some(location_of(lib_code, "Shape", &lib_uri))
+        );
+        assert_eq!(
+            main_state.defines["SIDES"].declaration_position,
+            Some(location_of(lib_code, "SIDES", &lib_uri))
+        );
+        assert_eq!(
+            main_state.functions["area"].declaration_position,
+            Some(location_of(lib_code, "area", &lib_uri))
+        );
+
+        // the call in main is a reference to area, regardless of which file asks
+        let expected_refs = vec![location_of(main_code, "area", &main_uri)];
+        assert_eq!(main_state.functions["area"].references, expected_refs);
+        assert_eq!(lib_state.functions["area"].references, expected_refs);
+
+        // symlinked folders are skipped, a link back to the root would loop
+        #[cfg(unix)]
+        {
+            let root = env::temp_dir().join(format!("cstyle_symlinks_{}", std::process::id()));
+            std::fs::create_dir_all(root.join("sub")).unwrap();
+            std::fs::write(root.join("sub/lib.cstyle"), lib_code).unwrap();
+            std::os::unix::fs::symlink(&root, root.join("sub/loop")).unwrap();
+
+            let profiles = vec![profiles::LangProfile {
+                config: profiles::ProfileConfig::default_profile(None),
+                lang_db: empty_lang_db.clone(),
+            }];
+            let mut scanned = workspace_index::WorkspaceIndex::default();
+            scanned.scan_folder(&root, &profiles);
+            let _ = std::fs::remove_dir_all(&root);
+            assert_eq!(scanned.files.len(), 1);
+        }
+    }
+
+    #[test]
+    fn validate_includes() {
+        let (empty_lang_db, _, _) = shared_sample_code();
+        let root = env::temp_dir().join(format!("cstyle_includes_{}", std::process::id()));
+        let sys_dir = root.join("sys");
+        std::fs::create_dir_all(&sys_dir).unwrap();
+
+        // shapes.h and util.h include each other
+        std::fs::write(
+            root.join("shapes.h"),
+            "#include \"util.h\"\nstruct Shape {\n    int sides;\n};\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("util.h"),
+            "#include \"shapes.h\"\n#define MAX_SIDES 8\nint shared_count;\nint area(Shape s) {\n    return 0;\n}\n",
+        )
+        .unwrap();
+        std::fs::write(sys_dir.join("sys.h"), "#define SYS_VERSION 2\n").unwrap();
+        // system includes are not searched for next to the including file
+        std::fs::write(root.join("local_only.h"), "#define LOCAL_ONLY 1\n").unwrap();
+
+        let main_code =
+            "#include \"shapes.h\"\n#include <sys.h>\n#include <local_only.h>\nvoid main() {}\n";
+        let main_uri = Url::from_file_path(root.join("main.cstyle")).unwrap();
+        let mut result = parser::parse(main_code.to_owned(), &main_uri, &empty_lang_db);
+        assert_eq!(
+            result.includes[0],
+            LangInclude {
+                path: "shapes.h".to_owned(),
+                system: false,
+                declaration_position: location_of(main_code, "\"shapes.h\"", &main_uri),
+            }
+        );
+        assert!(result.includes[1].system);
+
+        let mut index = workspace_index::WorkspaceIndex::default();
+        let include_paths = [sys_dir];
+        let unmerged = parser::parse(main_code.to_owned(), &main_uri, &empty_lang_db);
+        includes::merge_includes(
+            &mut result,
+            &main_uri,
+            &mut index,
+            0,
+            &empty_lang_db,
+            &include_paths,
+        );
+        let canonical_root = root.canonicalize().unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert!(result.types.contains_key("Shape"));
+        assert!(result.functions.contains_key("area"));
+        assert!(result.functions.contains_key("main"));
+        assert!(result.defines.contains_key("MAX_SIDES"));
+        assert!(result.defines.contains_key("SYS_VERSION"));
+        assert!(!result.defines.contains_key("LOCAL_ONLY"));
+        assert!(result.global_scope.vars.contains_key("shared_count"));
+        assert_eq!(
+            result.types["Shape"]
+                .declaration_position
+                .as_ref()
+                .unwrap()
+                .uri
+                .path()
+                .rsplit('/')
+                .next(),
+            Some("shapes.h")
+        );
+
+        // files read from disk are cached, so the deleted files are still merged until invalidated
+        let merge = |index: &mut workspace_index::WorkspaceIndex| {
+            let mut ps = parser::parse(main_code.to_owned(), &main_uri, &empty_lang_db);
+            includes::merge_includes(&mut ps, &main_uri, index, 0, &empty_lang_db, &include_paths);
+            return ps;
+        };
+        assert_eq!(index.included.len(), 3);
+        assert!(merge(&mut index).types.contains_key("Shape"));
+
+        // open (or indexed) files are used as they are, including unsaved changes
+        let shapes_uri = Url::from_file_path(canonical_root.join("shapes.h")).unwrap();
+        let edited = parser::parse(
+            "struct Polygon {\n    int sides;\n};\n".to_owned(),
+            &shapes_uri,
+            &empty_lang_db,
+        );
+        index.update(&shapes_uri, &edited, 0);
+        let ps = merge(&mut index);
+        assert!(ps.types.contains_key("Polygon"));
+        assert!(!ps.types.contains_key("Shape"));
+        assert!(
+            !ps.functions.contains_key("area"),
+            "util.h is no longer included"
+        );
+
+        // updating an indexed file drops its cached copy, invalidated files are read again
+        index.files.remove(&shapes_uri);
+        let sys_uri = Url::from_file_path(canonical_root.join("sys").join("sys.h")).unwrap();
+        index.invalidate(&sys_uri);
+        let ps = merge(&mut index);
+        assert_eq!(ps.types, unmerged.types);
+        assert_eq!(ps.defines, unmerged.defines);
+        assert!(!index.included.contains_key(&shapes_uri));
+    }
+
+    #[test]
+    fn validate_profiles() {
+        let (empty_lang_db, _, _) = shared_sample_code();
+        let profile = |name: &str, language_id: &str, ext: &str| profiles::LangProfile {
+            config: profiles::ProfileConfig {
+                name: name.to_owned(),
+                language_ids: vec![language_id.to_owned()],
+                extensions: vec![ext.to_owned()],
+                lang_db_path: None,
+            },
+            lang_db: empty_lang_db.clone(),
+        };
+        let all_profiles = vec![
+            profile("C-Style", "cstyle", "cstyle"),
+            profile("GLSL", "glsl", "frag"),
+            profile("Config", "cfgdsl", "cfg"),
+        ];
+
+        let shader_uri = Url::parse("file:///ws/light.frag").unwrap();
+        let script_uri = Url::parse("file:///ws/main.cstyle").unwrap();
+        let other_uri = Url::parse("file:///ws/notes.txt").unwrap();
+
+        // language id wins over the extension
+        assert_eq!(
+            profiles::profile_for(&all_profiles, &shader_uri, Some("cfgdsl")),
+            2
+        );
+        assert_eq!(profiles::profile_for(&all_profiles, &shader_uri, None), 1);
+        assert_eq!(
+            profiles::profile_for(&all_profiles, &shader_uri, Some("plaintext")),
+            1
+        );
+        // unmatched documents use the first profile
+        assert_eq!(profiles::profile_for(&all_profiles, &other_uri, None), 0);
+
+        // objects are only shared between files of the same profile
+        let mut index = workspace_index::WorkspaceIndex::default();
+        let shader = parser::parse("void shade() {}\n".to_owned(), &shader_uri, &empty_lang_db);
+        let mut script = parser::parse("void main() {}\n".to_owned(), &script_uri, &empty_lang_db);
+        index.update(&shader_uri, &shader, 1);
+        index.update(&script_uri, &script, 0);
+        index.merge_into(&mut script, &script_uri, 0);
+        assert!(!script.functions.contains_key("shade"));
+
+        // relative LangDB paths are made absolute so they can be watched
+        let cwd = env::current_dir().unwrap();
+        let existing = profiles::absolute_path(std::path::Path::new("./src/../lang_db.json"));
+        assert_eq!(existing, cwd.canonicalize().unwrap().join("lang_db.json"));
+        let missing = profiles::absolute_path(std::path::Path::new("missing/db.json"));
+        assert_eq!(missing, cwd.join("missing/db.json"));
+        assert!(Url::from_directory_path(missing.parent().unwrap()).is_ok());
+    }
+
     #[test]
     fn validate_lang_json() {
         let json_path = env::current_exe()
@@ -239,12 +568,55 @@ mod tests {
             .unwrap()
             .join("lang_db.json");
 
-        let file = File::open(json_path).unwrap();
+        LangDB::load(&json_path).unwrap();
+
+        let missing = env::temp_dir().join("cstyle_missing_lang_db.json");
+        let err = LangDB::load(&missing).unwrap_err();
+        assert!(err.starts_with("Couldn't open LangDB"), "{}", err);
+
+        let malformed = env::temp_dir().join(format!("cstyle_bad_db_{}.json", std::process::id()));
+        std::fs::write(&malformed, "{\"types\": 5}").unwrap();
+        let err = LangDB::load(&malformed).unwrap_err();
+        std::fs::remove_file(&malformed).unwrap();
+        assert!(err.starts_with("Couldn't parse LangDB"), "{}", err);
+    }
+
+    #[test]
+    fn validate_block_scopes() {
+        let (empty_lang_db, _, sample_uri) = shared_sample_code();
+        let src = r#"
+void loops() {
+    for (int i = 0; i < 2; i++) {
+        float inner = i;
+    }
+    for (float i = 0; i < 2; i++) {
+        i = i;
+    }
+    if (1) {
+        int branch = 1;
+    }
+}
+void a(int x) { x = x; } void b(float x) { x = x; }
+"#;
+        let ps = parser::parse(src.to_owned(), &sample_uri, &empty_lang_db);
+        let at = |line: u32, character: u32| Position { line, character };
+        let type_at = |name: &str, pos: Position| -> Option<String> {
+            return resolve_var(&ps.global_scope, name, pos).map(|lv| lv.primary_type.to_owned());
+        };
 
-        let reader = BufReader::new(file);
+        // each loop has its own `i`, and neither is visible after the loops
+        assert_eq!(type_at("i", at(3, 22)), Some("int".to_owned()));
+        assert_eq!(type_at("i", at(6, 12)), Some("float".to_owned()));
+        assert_eq!(type_at("i", at(8, 4)), None);
+        assert_eq!(type_at("inner", at(6, 8)), None);
+        assert_eq!(type_at("branch", at(9, 12)), Some("int".to_owned()));
+        assert_eq!(type_at("branch", at(10, 5)), None);
 
-        // Parse the JSON
-        let _: LangDB = serde_json::from_reader(reader).unwrap();
+        // functions on the same line are told apart by column
+        assert_eq!(type_at("x", at(12, 17)), Some("int".to_owned()));
+        assert_eq!(type_at("x", at(12, 44)), Some("float".to_owned()));
+        let vars = get_scoped_parse_state(&ps, at(12, 44)).vars;
+        assert_eq!(vars.get("x").unwrap().primary_type, "float");
     }
 
     #[test]
@@ -269,9 +641,37 @@ mod tests {
                 "param_var",
                 prov_semantic_tokens::LangSemanticToken::PARAMETER as u32,
             ),
+            (
+                "global_var",
+                prov_semantic_tokens::LangSemanticToken::VARIABLE as u32,
+            ),
+            (
+                "init_var",
+                prov_semantic_tokens::LangSemanticToken::VARIABLE as u32,
+            ),
+            (
+                "prim_var",
+                prov_semantic_tokens::LangSemanticToken::VARIABLE as u32,
+            ),
+            (
+                "cust_var",
+                prov_semantic_tokens::LangSemanticToken::VARIABLE as u32,
+            ),
+            (
+                "array_var",
+                prov_semantic_tokens::LangSemanticToken::VARIABLE as u32,
+            ),
+            (
+                "myField",
+                prov_semantic_tokens::LangSemanticToken::PROPERTY as u32,
+            ),
+            (
+                "arrayField",
+                prov_semantic_tokens::LangSemanticToken::PROPERTY as u32,
+            ),
         ]);
 
-        let sm = prov_semantic_tokens::get_sm_tokens(&result);
+        let sm = prov_semantic_tokens::get_sm_tokens(&result, &sample_uri, None);
         let mut found_strs = vec![];
         let mut row = 0;
         let mut col = 0;
@@ -288,10 +688,9 @@ mod tests {
             let token_str =
                 &sample_code.lines().nth(row as usize).unwrap()[col as usize..col_end as usize];
 
-            match token_str.parse::<f64>() {
-                Ok(_) => continue,
-                Err(_) => {}
-            };
+            if token_str.parse::<f64>().is_ok() {
+                continue;
+            }
 
             match correct_types.get(token_str) {
                 Some(token_type) => assert!(
@@ -314,6 +713,259 @@ mod tests {
         }
     }
 
+    // decodes semantic tokens into (line, col, text, token type, modifiers)
+    fn decode_sm_tokens(src: &str, sm: &[SemanticToken]) -> Vec<(u32, u32, String, u32, u32)> {
+        let mut tokens = vec![];
+        let mut row = 0;
+        let mut col = 0;
+        for token in sm {
+            row += token.delta_line;
+            if token.delta_line == 0 {
+                col += token.delta_start;
+            } else {
+                col = token.delta_start;
+            }
+            // columns and lengths are in utf-16 units
+            let line: Vec<u16> = src
+                .lines()
+                .nth(row as usize)
+                .unwrap()
+                .encode_utf16()
+                .collect();
+            let text =
+                String::from_utf16(&line[col as usize..(col + token.length) as usize]).unwrap();
+            tokens.push((
+                row,
+                col,
+                text,
+                token.token_type,
+                token.token_modifiers_bitset,
+            ));
+        }
+        return tokens;
+    }
+
+    #[test]
+    fn validate_scoped_semantic_tokens() {
+        let (empty_lang_db, _, sample_uri) = shared_sample_code();
+        let src = r#"
+struct Pair { int a; };
+enum Mode { FAST, SLOW };
+int count;
+int proto(int p);
+int scale(int value, struct Pair pair) {
+    int count = value * pair.a;
+    int mode = FAST;
+    return count + mode;
+}
+"#;
+        let ps = parser::parse(src.to_owned(), &sample_uri, &empty_lang_db);
+        let tokens = decode_sm_tokens(
+            src,
+            &prov_semantic_tokens::get_sm_tokens(&ps, &sample_uri, None),
+        );
+        let token_type = |line: u32, text: &str| -> Vec<u32> {
+            return tokens
+                .iter()
+                .filter(|t| t.0 == line && t.2 == text)
+                .map(|t| t.3)
+                .collect();
+        };
+
+        use prov_semantic_tokens::LangSemanticToken as T;
+        assert_eq!(token_type(1, "a"), vec![T::PROPERTY as u32]);
+        assert_eq!(
+            token_type(2, "FAST"),
+            vec![T::ENUM_MEMBER as u32],
+            "enumerator declaration"
+        );
+        assert_eq!(token_type(3, "count"), vec![T::VARIABLE as u32]);
+        assert_eq!(
+            token_type(4, "p"),
+            vec![T::PARAMETER as u32],
+            "prototype param"
+        );
+        assert_eq!(token_type(5, "value"), vec![T::PARAMETER as u32]);
+        assert_eq!(token_type(5, "pair"), vec![T::PARAMETER as u32]);
+
+        // params are coloured where they are used, not just where they are declared
+        assert_eq!(token_type(6, "value"), vec![T::PARAMETER as u32]);
+        assert_eq!(token_type(6, "pair"), vec![T::PARAMETER as u32]);
+        assert_eq!(token_type(6, "a"), vec![T::PROPERTY as u32]);
+        assert_eq!(
+            token_type(6, "count"),
+            vec![T::VARIABLE as u32],
+            "shadowing local"
+        );
+        assert_eq!(token_type(7, "FAST"), vec![T::ENUM_MEMBER as u32]);
+        assert_eq!(token_type(8, "count"), vec![T::VARIABLE as u32]);
+        assert_eq!(token_type(8, "mode"), vec![T::VARIABLE as u32]);
+    }
+
+    #[test]
+    fn validate_semantic_token_modifiers() {
+        let (mut lang_db, _, sample_uri) = shared_sample_code();
+        lang_db.functions.insert(
+            "oldSin".to_owned(),
+            LangFunc {
+                params: vec![],
+                return_type: "float".to_owned(),
+                variadic: false,
+                deprecated: true,
+                declaration_position: None,
+                references: vec![],
+                desc: "".to_owned(),
+            },
+        );
+        lang_db.builtin_vars.insert(
+            "gl_Position".to_owned(),
+            LangVar {
+                primary_type: "vec4".to_owned(),
+                type_qualifier_list: vec![],
+                declaration_position: None,
+                unused: false,
+            },
+        );
+        let src = r#"
+const int LIMIT = 4;
+int shade(int _skip, int used, int ignored) {
+    const float scale = 2;
+    int spare;
+    gl_Position = oldSin() * scale;
+    return used + LIMIT;
+}
+"#;
+        let ps = parser::parse(src.to_owned(), &sample_uri, &lang_db);
+        let tokens = decode_sm_tokens(
+            src,
+            &prov_semantic_tokens::get_sm_tokens(&ps, &sample_uri, None),
+        );
+        let modifiers = |line: u32, text: &str| -> u32 {
+            return tokens
+                .iter()
+                .find(|t| t.0 == line && t.2 == text)
+                .unwrap_or_else(|| panic!("no token for {}", text))
+                .4;
+        };
+
+        use prov_semantic_tokens::LangSemanticModifier as M;
+        assert_eq!(
+            modifiers(1, "LIMIT"),
+            M::DECLARATION.bit() | M::READONLY.bit()
+        );
+        assert_eq!(modifiers(2, "shade"), M::DECLARATION.bit());
+        assert_eq!(
+            modifiers(2, "_skip"),
+            M::DECLARATION.bit(),
+            "exempt from unused"
+        );
+        assert_eq!(modifiers(2, "used"), M::DECLARATION.bit());
+        assert_eq!(
+            modifiers(2, "ignored"),
+            M::DECLARATION.bit() | M::UNUSED.bit()
+        );
+        assert_eq!(
+            modifiers(3, "scale"),
+            M::DECLARATION.bit() | M::READONLY.bit()
+        );
+        assert_eq!(
+            modifiers(4, "spare"),
+            M::DECLARATION.bit() | M::UNUSED.bit()
+        );
+        assert_eq!(modifiers(5, "gl_Position"), M::DEFAULT_LIBRARY.bit());
+        assert_eq!(
+            modifiers(5, "oldSin"),
+            M::DEFAULT_LIBRARY.bit() | M::DEPRECATED.bit()
+        );
+        assert_eq!(modifiers(5, "scale"), M::READONLY.bit());
+        assert_eq!(modifiers(6, "used"), 0);
+        assert_eq!(modifiers(6, "LIMIT"), M::READONLY.bit());
+    }
+
+    #[test]
+    fn validate_semantic_tokens_range_and_delta() {
+        let (empty_lang_db, _, sample_uri) = shared_sample_code();
+        let src = r#"
+int first(int a) {
+    return a;
+}
+int second(int b) {
+    return b;
+}
+"#;
+        let ps = parser::parse(src.to_owned(), &sample_uri, &empty_lang_db);
+        let full = prov_semantic_tokens::get_sm_tokens(&ps, &sample_uri, None);
+
+        // only tokens on lines 4 and 5, the first is encoded relative to the start of the document
+        let range = Range {
+            start: Position {
+                line: 4,
+                character: 0,
+            },
+            end: Position {
+                line: 6,
+                character: 0,
+            },
+        };
+        let ranged = prov_semantic_tokens::get_sm_tokens(&ps, &sample_uri, Some(range));
+        let decoded: Vec<(u32, u32, String)> = decode_sm_tokens(src, &ranged)
+            .into_iter()
+            .map(|t| (t.0, t.1, t.2))
+            .collect();
+        assert_eq!(
+            decoded,
+            vec![
+                (4, 4, "second".to_owned()),
+                (4, 15, "b".to_owned()),
+                (5, 11, "b".to_owned())
+            ]
+        );
+
+        // no changes, no edits
+        assert!(prov_semantic_tokens::get_sm_token_edits(&full, &full).is_empty());
+
+        // renaming the second param only replaces its tokens
+        let changed_src = src.replace("b", "bb");
+        let changed = parser::parse(changed_src.to_owned(), &sample_uri, &empty_lang_db);
+        let new_full = prov_semantic_tokens::get_sm_tokens(&changed, &sample_uri, None);
+        let edits = prov_semantic_tokens::get_sm_token_edits(&full, &new_full);
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].start, 4 * 5); // first, a, a, second are unchanged
+
+        // applying the edit gives the new tokens
+        let mut applied = full.clone();
+        let start = edits[0].start as usize / 5;
+        let end = start + edits[0].delete_count as usize / 5;
+        applied.splice(start..end, edits[0].data.clone().unwrap());
+        assert_eq!(applied, new_full);
+
+        // columns count utf-16 units, `é` is 2 bytes but 1 unit, `😀` is 4 bytes but 2 units
+        let src = "int third(int c, int déjà) {\n    return /* é */ c + /* 😀 */ c;\n}\n";
+        let ps = parser::parse(src.to_owned(), &sample_uri, &empty_lang_db);
+        let range = Range {
+            start: Position::new(1, 20),
+            end: Position::new(2, 0),
+        };
+        let ranged = prov_semantic_tokens::get_sm_tokens(&ps, &sample_uri, Some(range));
+        assert_eq!(
+            decode_sm_tokens(src, &ranged)
+                .into_iter()
+                .map(|t| (t.0, t.1, t.2))
+                .collect::<Vec<_>>(),
+            vec![(1, 32, "c".to_owned())]
+        );
+        let full = prov_semantic_tokens::get_sm_tokens(&ps, &sample_uri, None);
+        let deja = decode_sm_tokens(src, &full)
+            .into_iter()
+            .find(|t| t.2 == "déjà")
+            .unwrap();
+        assert_eq!(
+            (deja.0, deja.1),
+            (0, 21),
+            "the length is 4 units, not 6 bytes"
+        );
+    }
+
     #[test]
     fn validate_ident_seq_extract() {
         let tests = vec![
@@ -358,12 +1010,7 @@ mod tests {
             .unwrap()
             .join("lang_db.json");
 
-        let file = File::open(json_path).unwrap();
-
-        let reader = BufReader::new(file);
-
-        // Parse the JSON
-        let real_lang_db: LangDB = serde_json::from_reader(reader).unwrap();
+        let real_lang_db = LangDB::load(&json_path).unwrap();
         let (_, sample_code, sample_uri) = shared_sample_code();
         let result = parser::parse(sample_code.to_owned(), &sample_uri, &real_lang_db);
 
@@ -397,7 +1044,7 @@ mod tests {
                 character: 8 + 17,
             },
             "cust_var",
-            "MyStruct  cust_var",
+            "MyStruct cust_var",
         )];
 
         for (hover_pos, hover_word, hover_txt) in hovers {
@@ -419,24 +1066,1221 @@ mod tests {
     }
 
     #[test]
-    fn validate_completions() {}
+    fn validate_signature_help() {
+        let (empty_lang_db, _, sample_uri) = shared_sample_code();
+        let code = "float mix2(float a, float b[2], float t) {\n    return a;\n}\nvoid main() {\n    mix2(1, 2, 3);\n    mix2(mix2(1, 2, 3), \n}\n";
+        let result = parser::parse(code.to_owned(), &sample_uri, &empty_lang_db);
+
+        let tests = vec![
+            // complete call, after the second comma
+            (Position::new(4, 15), Some(2)),
+            // complete call, right after the '('
+            (Position::new(4, 9), Some(0)),
+            // outside of the call
+            (Position::new(4, 4), None),
+            // incomplete outer call, after the nested call
+            (Position::new(5, 24), Some(1)),
+            // inside the nested call
+            (Position::new(5, 17), Some(1)),
+        ];
+
+        for (position, expected_param) in tests {
+            let help = prov_signature_help::get_signature_help(
+                &get_scoped_parse_state(&result, position),
+                position,
+            );
+            match expected_param {
+                None => assert!(help.is_none(), "unexpected help at {:?}", position),
+                Some(expected_param) => {
+                    let help = help.unwrap();
+                    assert_eq!(
+                        help.active_parameter,
+                        Some(expected_param),
+                        "{:?}",
+                        position
+                    );
+
+                    let signature = &help.signatures[0];
+                    assert_eq!(signature.label, "float mix2(float a, float b[], float t)");
+                    let offsets: Vec<[u32; 2]> = signature
+                        .parameters
+                        .as_ref()
+                        .unwrap()
+                        .iter()
+                        .map(|p| match p.label {
+                            ParameterLabel::LabelOffsets(offsets) => offsets,
+                            _ => panic!("expected label offsets"),
+                        })
+                        .collect();
+                    assert_eq!(offsets, vec![[11, 18], [20, 29], [31, 38]]);
+                }
+            }
+        }
+    }
 
     #[test]
-    fn validate_folding() {
+    fn validate_syntax_diagnostics() {
+        let (empty_lang_db, sample_code, sample_uri) = shared_sample_code();
+        let result = parser::parse(sample_code.to_owned(), &sample_uri, &empty_lang_db);
+        // the vec types come from a LangDB, so only those are errors here
+        let errors: Vec<String> = diagnostics::get_diagnostics(&result, &sample_uri, "test")
+            .iter()
+            .filter(|d| d.severity == Some(DiagnosticSeverity::ERROR))
+            .map(|d| d.message.to_owned())
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                "unknown type 'vec3'",
+                "unknown type 'vec2'",
+                "unknown type 'vec4'",
+            ]
+        );
+
+        let code = "void main() {\n    return 0\n}\nint @@;\n";
+        let result = parser::parse(code.to_owned(), &sample_uri, &empty_lang_db);
+        let items = diagnostics::get_diagnostics(&result, &sample_uri, "test");
+
+        let summary: Vec<(u32, String)> = items
+            .iter()
+            .map(|d| (d.range.start.line, d.message.to_owned()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (1, "expected ';'".to_owned()),
+                (3, "syntax error, unexpected '@@'".to_owned()),
+            ]
+        );
+        assert!(items
+            .iter()
+            .all(|d| d.severity == Some(DiagnosticSeverity::ERROR)
+                && d.source == Some("test".to_owned())));
+    }
+
+    #[test]
+    fn validate_unused_diagnostics() {
+        let (empty_lang_db, _, sample_uri) = shared_sample_code();
+        let code = r#"
+        int global_unused;
+        int helper(int used_param, int unused_param, int _ignored) {
+            int read_var = used_param;
+            int write_only;
+            write_only = 3;
+            int counter = 0;
+            counter += 1;
+            return read_var;
+        }
+        void main(int argc) {
+            for (int i = 0; i < 2; i++) {
+                helper(i, 0, 0);
+            }
+        }
+        "#;
+        let result = parser::parse(code.to_owned(), &sample_uri, &empty_lang_db);
+        let items = diagnostics::get_diagnostics(&result, &sample_uri, "test");
+
+        let summary: Vec<(Range, String)> = items
+            .iter()
+            .map(|d| (d.range, d.message.to_owned()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    location_of(code, "unused_param", &sample_uri).range,
+                    "parameter 'unused_param' is never used".to_owned()
+                ),
+                (
+                    location_of(code, "write_only", &sample_uri).range,
+                    "variable 'write_only' is never used".to_owned()
+                ),
+            ]
+        );
+        assert!(items
+            .iter()
+            .all(|d| d.severity == Some(DiagnosticSeverity::WARNING)
+                && d.tags == Some(vec![DiagnosticTag::UNNECESSARY])));
+
+        // parameters of function pointers and prototypes only name the parameter
+        let code = r#"
+int cmp(int x, int y) { return x - y; }
+int proto(int unnamed_use);
+int apply(int (*fn)(int value)) { return fn(1); }
+void main() {
+    int (*cb)(int v);
+    int (*fp)(int a, int b) = cmp;
+    cb = fp;
+    apply(cb);
+}
+"#;
+        let result = parser::parse(code.to_owned(), &sample_uri, &empty_lang_db);
+        let items = diagnostics::get_diagnostics(&result, &sample_uri, "test");
+        assert_eq!(
+            items.iter().map(|d| &d.message).collect::<Vec<_>>(),
+            Vec::<&String>::new()
+        );
+        assert!(result.global_scope.vars.is_empty());
+        let apply = result.functions.get("apply").unwrap();
+        assert_eq!(apply.params.len(), 1);
+        assert_eq!(apply.params[0].0, "fn");
+    }
+
+    #[test]
+    fn validate_multiple_declarators() {
+        let (empty_lang_db, _, sample_uri) = shared_sample_code();
+        let code = r#"
+struct Vec { float x, y, z; };
+int g1, g2[3];
+void main() {
+    int a, b = 2, c[4];
+    a = b + c[0];
+}
+"#;
+        let result = parser::parse(code.to_owned(), &sample_uri, &empty_lang_db);
+        let at = |line: u32, character: u32| Position { line, character };
+
+        let fields = &result.types.get("Vec").unwrap().fields;
+        let mut names: Vec<&String> = fields.keys().collect();
+        names.sort();
+        assert_eq!(names, vec!["x", "y", "z"]);
+        let z = fields
+            .get("z")
+            .unwrap()
+            .declaration_position
+            .as_ref()
+            .unwrap();
+        assert_eq!(z.range.start, at(1, 25));
+
+        let globals = &result.global_scope.vars;
+        assert_eq!(
+            globals.get("g1").unwrap().type_qualifier_list,
+            Vec::<String>::new()
+        );
+        assert_eq!(globals.get("g2").unwrap().type_qualifier_list, vec!["[]"]);
+
+        // each declarator has its own qualifiers and location
+        for (name, col, qualifiers) in [("a", 8, vec![]), ("b", 11, vec![]), ("c", 18, vec!["[]"])]
+        {
+            let lv = resolve_var(&result.global_scope, name, at(5, 4)).unwrap();
+            assert_eq!(lv.primary_type, "int");
+            assert_eq!(lv.type_qualifier_list, qualifiers, "{}", name);
+            assert_eq!(
+                lv.declaration_position.as_ref().unwrap().range.start,
+                at(4, col),
+                "{}",
+                name
+            );
+        }
+
+        // later declarators are declaration sites too
+        let highlights = prov_highlight::get_highlights(&result, &sample_uri, at(4, 11)).unwrap();
+        assert_eq!(
+            highlights.iter().map(|h| h.kind).collect::<Vec<_>>(),
+            vec![
+                Some(DocumentHighlightKind::WRITE),
+                Some(DocumentHighlightKind::READ)
+            ]
+        );
+        let items = diagnostics::get_diagnostics(&result, &sample_uri, "test");
+        let messages: Vec<&str> = items.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec!["variable 'a' is never used"]);
+    }
+
+    #[test]
+    fn validate_declaration_order() {
+        let (empty_lang_db, _, sample_uri) = shared_sample_code();
+        let code = r#"
+int shadowed;
+void main() {
+    int early = late + shadowed;
+    float shadowed = 2;
+    int late = 1;
+    shadowed = late;
+}
+"#;
+        let result = parser::parse(code.to_owned(), &sample_uri, &empty_lang_db);
+        let at = |line: u32, character: u32| Position { line, character };
+
+        // locals are only visible after they are declared
+        let vars = get_scoped_parse_state(&result, at(3, 16)).vars;
+        assert!(!vars.contains_key("late"));
+        assert_eq!(vars.get("shadowed").unwrap().primary_type, "int");
+        let vars = get_scoped_parse_state(&result, at(6, 4)).vars;
+        assert!(vars.contains_key("late"));
+        assert_eq!(vars.get("shadowed").unwrap().primary_type, "float");
+
+        // the global is used until the local that shadows it is declared
+        let before = resolve_var(&result.global_scope, "shadowed", at(3, 23)).unwrap();
+        assert_eq!(before.primary_type, "int");
+        assert!(resolve_var(&result.global_scope, "late", at(3, 16)).is_none());
+
+        let items = diagnostics::get_diagnostics(&result, &sample_uri, "test");
+        let summary: Vec<(Range, Option<DiagnosticSeverity>, String)> = items
+            .iter()
+            .filter(|d| d.message.contains("late"))
+            .map(|d| (d.range, d.severity, d.message.to_owned()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![(
+                Range {
+                    start: at(3, 16),
+                    end: at(3, 20)
+                },
+                Some(DiagnosticSeverity::WARNING),
+                "'late' is used before it is declared".to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn validate_unresolved_diagnostics() {
+        let (empty_lang_db, _, sample_uri) = shared_sample_code();
+        let code = r#"
+        #define LIMIT 4
+        #define SQUARE(x) ((x) * (x))
+        #ifdef DEBUG_BUILD
+        #endif
+        struct Point {
+            int x;
+        };
+        typedef struct Point PointAlias;
+        enum Color { RED, GREEN };
+        int counter;
+        int helper(int value) {
+            return SQUARE(value) + LIMIT + RED;
+        }
+        void main() {
+            Point p;
+            PointAlias q;
+            Pont r;
+            int *ptr = &counter;
+            int first = 1, second = first;
+            p.x = helpr(countr) + second + *ptr + q.x + missing_thing;
+        }
+        "#;
+        let result = parser::parse(code.to_owned(), &sample_uri, &empty_lang_db);
+        let items = diagnostics::get_diagnostics(&result, &sample_uri, "test");
+
+        let summary: Vec<(Range, String)> = items
+            .iter()
+            .filter(|d| d.severity == Some(DiagnosticSeverity::ERROR))
+            .map(|d| (d.range, d.message.to_owned()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    location_of(code, "Pont", &sample_uri).range,
+                    "unknown type 'Pont', did you mean 'Point'?".to_owned()
+                ),
+                (
+                    location_of(code, "helpr", &sample_uri).range,
+                    "undeclared identifier 'helpr', did you mean 'helper'?".to_owned()
+                ),
+                (
+                    location_of(code, "countr", &sample_uri).range,
+                    "undeclared identifier 'countr', did you mean 'counter'?".to_owned()
+                ),
+                (
+                    location_of(code, "missing_thing", &sample_uri).range,
+                    "undeclared identifier 'missing_thing'".to_owned()
+                ),
+            ]
+        );
+
+        // locals of other functions and ended loops are out of scope
+        let code = r#"
+void first() { int count; count = 1; }
+void second() { count = 2; }
+void third() {
+    for (int i = 0; i < 2; i++) {}
+    i = 3;
+}
+"#;
+        let result = parser::parse(code.to_owned(), &sample_uri, &empty_lang_db);
+        let items = diagnostics::get_diagnostics(&result, &sample_uri, "test");
+        let summary: Vec<(Range, String)> = items
+            .iter()
+            .filter(|d| d.severity == Some(DiagnosticSeverity::ERROR))
+            .map(|d| (d.range, d.message.to_owned()))
+            .collect();
+        let at = |line: u32, character: u32| Position { line, character };
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    Range {
+                        start: at(2, 16),
+                        end: at(2, 21)
+                    },
+                    "undeclared identifier 'count'".to_owned()
+                ),
+                (
+                    Range {
+                        start: at(5, 4),
+                        end: at(5, 5)
+                    },
+                    "undeclared identifier 'i'".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_qualifiers() {
+        let (mut lang_db, _, sample_uri) = shared_sample_code();
+        for name in ["int", "char"] {
+            lang_db.types.insert(
+                name.to_owned(),
+                LangType {
+                    fields: HashMap::new(),
+                    declaration_position: None,
+                    desc: "".to_owned(),
+                    builtin: true,
+                },
+            );
+        }
+        lang_db.type_rules.int_literal_type = Some("int".to_owned());
+
+        let code = r#"
+static const int limit = 2;
+const char *name;
+char *const fixed = 0;
+int *arr[2];
+int (*callback)(int);
+int proto(int p);
+int *make(int count, char **argv) {
+    int *first = arr[0];
+    int wrong = arr[1];
+    int (*fp)(int a, int limit);
+    return first;
+}
+"#;
+        let result = parser::parse(code.to_owned(), &sample_uri, &lang_db);
+        let globals = &result.global_scope.vars;
+        let label = |name: &str| globals.get(name).unwrap().declaration_label(name);
+
+        assert_eq!(label("limit"), "static const int limit");
+        assert_eq!(label("name"), "const char *name");
+        assert_eq!(label("fixed"), "char *const fixed");
+        assert_eq!(label("arr"), "int *arr[]");
+        assert_eq!(label("callback"), "int (*callback)()");
+        assert!(!globals.contains_key("proto"), "prototypes aren't vars");
+
+        let make = result.functions.get("make").unwrap();
+        assert_eq!(make.return_type, "int*");
+        assert_eq!(make.params[1].1.declaration_label("argv"), "char **argv");
+        assert_eq!(
+            prov_signature_help::build_signature("make", make).label,
+            "int* make(int count, char **argv)"
+        );
+
+        let hover_pos = Position::new(8, 10);
+        match prov_hover::get_hover(&get_scoped_parse_state(&result, hover_pos), hover_pos)
+            .unwrap()
+            .contents
+        {
+            HoverContents::Markup(markup) => assert_eq!(markup.value, "int *first"),
+            _ => panic!("unexpected hover content"),
+        }
+
+        // indexing an array of pointers gives a pointer, `0` is a null pointer
+        let items = diagnostics::get_diagnostics(&result, &sample_uri, "test");
+        let messages: Vec<&str> = items
+            .iter()
+            .filter(|d| d.severity == Some(DiagnosticSeverity::ERROR))
+            .map(|d| d.message.as_str())
+            .collect();
+        assert_eq!(
+            messages,
+            vec!["cannot initialize 'wrong' of type 'int' with a value of type 'int*'"]
+        );
+
+        // only vars that can't be reassigned are readonly
+        let tokens = decode_sm_tokens(
+            code,
+            &prov_semantic_tokens::get_sm_tokens(&result, &sample_uri, None),
+        );
+        let readonly: Vec<&str> = tokens
+            .iter()
+            .filter(|t| t.4 & prov_semantic_tokens::LangSemanticModifier::READONLY.bit() != 0)
+            .map(|t| t.2.as_str())
+            .collect();
+        assert_eq!(readonly, vec!["limit", "fixed"]);
+
+        // params of a function pointer aren't vars, so they are never unused or readonly
+        let fp = resolve_var(&result.global_scope, "fp", Position::new(11, 4)).unwrap();
+        assert_eq!(fp.declaration_label("fp"), "int (*fp)()");
+        assert!(resolve_var(&result.global_scope, "a", Position::new(11, 4)).is_none());
+        let fp_params: Vec<(&str, u32, u32)> = tokens
+            .iter()
+            .filter(|t| t.0 == 10 && (t.2 == "a" || t.2 == "limit"))
+            .map(|t| (t.2.as_str(), t.3, t.4))
+            .collect();
+        let param = prov_semantic_tokens::LangSemanticToken::PARAMETER as u32;
+        let declaration = prov_semantic_tokens::LangSemanticModifier::DECLARATION.bit();
+        assert_eq!(
+            fp_params,
+            vec![("a", param, declaration), ("limit", param, declaration)]
+        );
+        assert!(items
+            .iter()
+            .all(|d| !d.message.contains("'a'") && !d.message.contains("'limit'")));
+    }
+
+    #[test]
+    fn validate_type_diagnostics() {
+        let (mut lang_db, _, sample_uri) = shared_sample_code();
+        for name in ["void", "bool", "int", "float", "char"] {
+            lang_db.types.insert(
+                name.to_owned(),
+                LangType {
+                    fields: HashMap::new(),
+                    declaration_position: None,
+                    desc: "".to_owned(),
+                    builtin: true,
+                },
+            );
+        }
+        lang_db.type_rules = TypeRules {
+            implicit_conversions: HashMap::from([
+                ("int".to_owned(), vec!["float".to_owned()]),
+                ("char".to_owned(), vec!["int".to_owned()]),
+            ]),
+            boolean_type: Some("bool".to_owned()),
+            int_literal_type: Some("int".to_owned()),
+            float_literal_type: Some("float".to_owned()),
+            char_literal_type: Some("char".to_owned()),
+            string_literal_type: Some("char[]".to_owned()),
+        };
+
+        let code = r#"
+        struct Pair {
+            int first;
+            float second;
+        };
+        float scale(float value, int times) {
+            return value * times;
+        }
+        int broken() {
+            return "text";
+        }
+        void main() {
+            struct Pair pair;
+            float ok = 'c' + 2;
+            int bad_init = 2.5;
+            char name[4] = "abc";
+            bool flag = pair.first < 3;
+            pair.first = pair.second;
+            scale(pair.first, name);
+            name[0] = 'x';
+        }
+        "#;
+        let result = parser::parse(code.to_owned(), &sample_uri, &lang_db);
+        let items = diagnostics::get_diagnostics(&result, &sample_uri, "test");
+
+        let mut name_arg = location_of(code, "name);", &sample_uri).range;
+        name_arg.end.character -= 2;
+
+        let summary: Vec<(Range, String)> = items
+            .iter()
+            .filter(|d| d.severity == Some(DiagnosticSeverity::ERROR))
+            .map(|d| (d.range, d.message.to_owned()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    location_of(code, "\"text\"", &sample_uri).range,
+                    "cannot return a value of type 'char[]' from a function returning 'int'"
+                        .to_owned()
+                ),
+                (
+                    location_of(code, "2.5", &sample_uri).range,
+                    "cannot initialize 'bad_init' of type 'int' with a value of type 'float'"
+                        .to_owned()
+                ),
+                (
+                    location_of(code, "pair.second", &sample_uri).range,
+                    "cannot assign a value of type 'float' to 'int'".to_owned()
+                ),
+                (
+                    name_arg,
+                    "cannot pass a value of type 'char[]' to parameter 'times' of type 'int'"
+                        .to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_arity_diagnostics() {
+        let (mut lang_db, _, sample_uri) = shared_sample_code();
+        lang_db.functions.insert(
+            "print".to_owned(),
+            LangFunc {
+                params: vec![(
+                    "format".to_owned(),
+                    LangVar {
+                        primary_type: "char".to_owned(),
+                        type_qualifier_list: vec!["[]".to_owned()],
+                        declaration_position: None,
+                        unused: false,
+                    },
+                )],
+                return_type: "int".to_owned(),
+                variadic: true,
+                deprecated: false,
+                declaration_position: None,
+                references: vec![],
+                desc: "".to_owned(),
+            },
+        );
+
+        let code = r#"
+        int add(int a, int b) {
+            return a + b;
+        }
+        int sum(int count, ...) {
+            return count;
+        }
+        void main() {
+            add(1, 2);
+            add(1);
+            add(1, 2, 3, 4);
+            sum(3, 1, 2, 3);
+            sum();
+            print("%d %d", 1, 2);
+            print("plain");
+            print();
+        }
+        "#;
+        let result = parser::parse(code.to_owned(), &sample_uri, &lang_db);
+        let items = diagnostics::get_diagnostics(&result, &sample_uri, "test");
+
+        let summary: Vec<(u32, String)> = items
+            .iter()
+            .filter(|d| d.severity == Some(DiagnosticSeverity::ERROR))
+            .map(|d| (d.range.start.line, d.message.to_owned()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    9,
+                    "too few arguments to 'add', expected 'int add(int a, int b)'".to_owned()
+                ),
+                (
+                    10,
+                    "too many arguments to 'add', expected 'int add(int a, int b)'".to_owned()
+                ),
+                (
+                    12,
+                    "too few arguments to 'sum', expected 'int sum(int count, ...)'".to_owned()
+                ),
+                (
+                    15,
+                    "too few arguments to 'print', expected 'int print(char format[], ...)'"
+                        .to_owned()
+                ),
+            ]
+        );
+
+        // extra arguments are highlighted, not the whole call
+        let too_many = items
+            .iter()
+            .find(|d| d.message.starts_with("too many"))
+            .unwrap();
+        let mut extra = location_of(code, "3, 4", &sample_uri).range;
+        extra.end.character = extra.start.character + 4;
+        assert_eq!(too_many.range, extra);
+    }
+
+    #[test]
+    fn validate_rename() {
+        let (mut lang_db, _, sample_uri) = shared_sample_code();
+        lang_db.control.push("while".to_owned());
+        lang_db.builtin_vars.insert(
+            "gl_Position".to_owned(),
+            LangVar {
+                primary_type: "int".to_owned(),
+                type_qualifier_list: vec![],
+                declaration_position: None,
+                unused: false,
+            },
+        );
+
+        let code = r#"
+        #define LIMIT 4
+        struct Point {
+            int px;
+            int py;
+        };
+        int total;
+        int first(int count) {
+            int value = count + LIMIT;
+            return value;
+        }
+        int second(struct Point pt) {
+            int value = pt.px;
+            total = value + gl_Position;
+            return value + first(pt.py);
+        }
+        "#;
+        let ps = parser::parse(code.to_owned(), &sample_uri, &lang_db);
+
+        // renames the position to new_name, returning the (line, character) of each edit
+        let rename = |line: u32, character: u32, new_name: &str| {
+            let position = Position { line, character };
+            let (symbol, _) = prov_rename::prepare_rename(&ps, &sample_uri, position)?;
+            let locations = symbols::find_occurrences(&ps, &sample_uri, &symbol);
+            prov_rename::check_new_name(&ps, &symbol, new_name, &locations)?;
+            let mut edits: Vec<(u32, u32)> = locations
+                .iter()
+                .map(|l| (l.range.start.line, l.range.start.character))
+                .collect();
+            edits.sort();
+            return Ok::<Vec<(u32, u32)>, String>(edits);
+        };
+
+        // the local in second is left alone when renaming the local in first
+        assert_eq!(rename(8, 16, "result"), Ok(vec![(8, 16), (9, 19)]));
+        assert_eq!(rename(13, 20, "v"), Ok(vec![(12, 16), (13, 20), (14, 19)]));
+        assert_eq!(rename(7, 22, "n"), Ok(vec![(7, 22), (8, 24)]));
+
+        // globals, functions, defines, structs and fields
+        assert_eq!(rename(13, 12, "sum"), Ok(vec![(6, 12), (13, 12)]));
+        assert_eq!(rename(14, 27, "start"), Ok(vec![(7, 12), (14, 27)]));
+        assert_eq!(rename(1, 16, "MAX"), Ok(vec![(1, 16), (8, 32)]));
+        assert_eq!(rename(2, 15, "Vec"), Ok(vec![(2, 15), (11, 26)]));
+        assert_eq!(rename(14, 36, "y"), Ok(vec![(4, 16), (14, 36)]));
+
+        // refused renames
+        assert_eq!(
+            rename(13, 28, "x"),
+            Err("can't rename builtin 'gl_Position'".to_owned())
+        );
+        assert_eq!(
+            rename(8, 16, "count"),
+            Err("'count' is already declared".to_owned())
+        );
+        assert_eq!(
+            rename(8, 16, "total"),
+            Err("'total' is already declared".to_owned())
+        );
+        assert_eq!(
+            rename(14, 36, "px"),
+            Err("'px' is already declared".to_owned())
+        );
+        assert_eq!(
+            rename(8, 16, "while"),
+            Err("'while' is a keyword".to_owned())
+        );
+        assert_eq!(
+            rename(8, 16, "2x"),
+            Err("'2x' is not a valid identifier".to_owned())
+        );
+
+        // collisions don't depend on declaration order
+        let code = r#"
+void main() {
+    int a = 1;
+    a = a;
+    int b = 2;
+    b = b;
+    for (int c = 0; c < 2; c++) {}
+}
+"#;
+        let ps = parser::parse(code.to_owned(), &sample_uri, &lang_db);
+        for (line, character, new_name) in [(2, 8, "b"), (4, 8, "a"), (2, 8, "c")] {
+            let position = Position { line, character };
+            let (symbol, _) = prov_rename::prepare_rename(&ps, &sample_uri, position).unwrap();
+            let locations = symbols::find_occurrences(&ps, &sample_uri, &symbol);
+            assert_eq!(
+                prov_rename::check_new_name(&ps, &symbol, new_name, &locations),
+                Err(format!("'{}' is already declared", new_name))
+            );
+        }
+    }
+
+    #[test]
+    fn validate_references() {
+        let (empty_lang_db, _, sample_uri) = shared_sample_code();
+        let code = r#"
+        #define SIZE 3
+        struct Cell {
+            int weight;
+        };
+        int grid;
+        int count(struct Cell cells[]) {
+            int total = 0;
+            for (int i = 0; i < SIZE; i++) {
+                total += cells[i].weight;
+            }
+            return total + grid;
+        }
+        void main() {
+            struct Cell cells[SIZE];
+            for (int i = 0; i < SIZE; i++) {
+                cells[i].weight = i;
+            }
+            grid = count(cells);
+        }
+        "#;
+        let ps = parser::parse(code.to_owned(), &sample_uri, &empty_lang_db);
+
+        // the (line, character) of every reference to the symbol at the position
+        let references = |line: u32, character: u32, include_declaration: bool| {
+            let (symbol, _) = symbols::symbol_at(&ps, Position { line, character }).unwrap();
+            let occurrences = symbols::find_occurrences(&ps, &sample_uri, &symbol);
+            return prov_goto::goto_references(&ps, &symbol, occurrences, include_declaration)
+                .iter()
+                .map(|l| (l.range.start.line, l.range.start.character))
+                .collect::<Vec<(u32, u32)>>();
+        };
+
+        // loop counters in different functions are distinct
+        assert_eq!(
+            references(8, 21, true),
+            vec![(8, 21), (8, 28), (8, 38), (9, 31)]
+        );
+        assert_eq!(
+            references(16, 22, false),
+            vec![(15, 28), (15, 38), (16, 22), (16, 34)]
+        );
+
+        // parameters, globals, structs, fields and defines
+        assert_eq!(references(9, 25, false), vec![(9, 25)]);
+        assert_eq!(references(5, 12, false), vec![(11, 27), (18, 12)]);
+        assert_eq!(references(2, 15, true), vec![(2, 15), (6, 25), (14, 19)]);
+        assert_eq!(references(3, 16, false), vec![(9, 34), (16, 25)]);
+        assert_eq!(references(1, 16, false), vec![(8, 32), (14, 30), (15, 32)]);
+
+        // functions include calls and the declaration when asked for
+        assert_eq!(references(18, 20, true), vec![(6, 12), (18, 19)]);
+
+        // globals declared in a header are found in the workspace files that include it
+        let root = env::temp_dir().join(format!("cstyle_references_{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let root = root.canonicalize().unwrap();
+        std::fs::write(root.join("shared.h"), "int shared_total;\n").unwrap();
+        std::fs::write(
+            root.join("user.cstyle"),
+            "#include \"shared.h\"\nvoid bump() {\n    int other = 0;\n    shared_total = other;\n}\n",
+        )
+        .unwrap();
+        let mut index = workspace_index::WorkspaceIndex::default();
+        index.index_file(&root.join("shared.h"), 0, &empty_lang_db);
+        index.index_file(&root.join("user.cstyle"), 0, &empty_lang_db);
+
+        let main_code = "#include \"shared.h\"\nvoid main() {\n    shared_total = 2;\n}\n";
+        let main_uri = Url::from_file_path(root.join("main.cstyle")).unwrap();
+        let mut ps = parser::parse(main_code.to_owned(), &main_uri, &empty_lang_db);
+        includes::merge_includes(&mut ps, &main_uri, &mut index, 0, &empty_lang_db, &[]);
+
+        let position = Position::new(2, 4);
+        let (symbol, _) = prov_rename::prepare_rename(&ps, &main_uri, position).unwrap();
+        assert!(symbols::is_global_var(&ps, &symbol));
+        let (local, _) = symbols::symbol_at(&ps, Position::new(1, 5)).unwrap();
+        assert!(
+            !symbols::is_global_var(&ps, &local),
+            "functions aren't vars"
+        );
+
+        let mut open = HashMap::new();
+        open.insert(main_uri.clone(), ps);
+        let folders = vec![root.clone()];
+        let file_positions = |found: Vec<Location>| {
+            return found
+                .iter()
+                .map(|l| {
+                    let file = l.uri.path().rsplit('/').next().unwrap().to_owned();
+                    (file, l.range.start.line, l.range.start.character)
+                })
+                .collect::<Vec<(String, u32, u32)>>();
+        };
+        let found = symbols::find_indexed_occurrences(
+            &mut index,
+            &symbol,
+            0,
+            &empty_lang_db,
+            &[],
+            &open,
+            &folders,
+        );
+        assert_eq!(
+            file_positions(found),
+            vec![
+                ("shared.h".to_owned(), 0, 4),
+                ("user.cstyle".to_owned(), 3, 4)
+            ]
+        );
+
+        // a global is the same across files when defined in one and declared `extern` in a header,
+        // the header is found through the files that include it, static globals are left alone
+        std::fs::write(root.join("g.h"), "extern int counter;\n").unwrap();
+        std::fs::write(
+            root.join("b.cstyle"),
+            "#include \"g.h\"\nvoid set() {\n    counter = 2;\n}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("c.cstyle"),
+            "static int counter;\nvoid reset() {\n    counter = 0;\n}\n",
+        )
+        .unwrap();
+        let mut index = workspace_index::WorkspaceIndex::default();
+        index.index_file(&root.join("b.cstyle"), 0, &empty_lang_db);
+        index.index_file(&root.join("c.cstyle"), 0, &empty_lang_db);
+
+        let a_code = "int counter = 0;\nvoid step() {\n    counter++;\n}\n";
+        let a_uri = Url::from_file_path(root.join("a.cstyle")).unwrap();
+        let a_state = parser::parse(a_code.to_owned(), &a_uri, &empty_lang_db);
+        let (symbol, _) =
+            prov_rename::prepare_rename(&a_state, &a_uri, Position::new(2, 4)).unwrap();
+        let mut open = HashMap::new();
+        open.insert(a_uri.clone(), a_state);
+        let found = symbols::find_indexed_occurrences(
+            &mut index,
+            &symbol,
+            0,
+            &empty_lang_db,
+            &[],
+            &open,
+            &folders,
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(
+            file_positions(found),
+            vec![("b.cstyle".to_owned(), 2, 4), ("g.h".to_owned(), 0, 11)]
+        );
+    }
+
+    #[test]
+    fn validate_document_highlight() {
+        let (empty_lang_db, _, sample_uri) = shared_sample_code();
+        let code = r#"
+        #ifdef DEBUG
+        int level = 2;
+        #else
+        int level = 0;
+        #endif
+        struct Pair {
+            int left;
+        };
+        void update(struct Pair pair) {
+            int count = level;
+            count = count + 1;
+            count++;
+            pair.left = count;
+        }
+        void other() {
+            int count = 5;
+        }
+        "#;
+        let ps = parser::parse(code.to_owned(), &sample_uri, &empty_lang_db);
+
+        // (line, character, is_write) for the highlights at the position
+        let highlights = |line: u32, character: u32| {
+            return prov_highlight::get_highlights(&ps, &sample_uri, Position { line, character })
+                .unwrap_or_default()
+                .iter()
+                .map(|h| {
+                    (
+                        h.range.start.line,
+                        h.range.start.character,
+                        h.kind == Some(DocumentHighlightKind::WRITE),
+                    )
+                })
+                .collect::<Vec<(u32, u32, bool)>>();
+        };
+
+        assert_eq!(
+            highlights(11, 12),
+            vec![
+                (10, 16, true),
+                (11, 12, true),
+                (11, 20, false),
+                (12, 12, true),
+                (13, 24, false),
+            ]
+        );
+        assert_eq!(highlights(13, 17,), vec![(7, 16, true), (13, 17, true)]);
+        assert_eq!(highlights(9, 27), vec![(6, 15, true), (9, 27, false)]);
+
+        // directive groups and brace pairs
+        let directives = vec![(1, 8, false), (3, 8, false), (5, 8, false)];
+        assert_eq!(highlights(3, 9), directives);
+        assert_eq!(highlights(5, 14), directives);
+        assert_eq!(highlights(9, 38), vec![(9, 38, false), (14, 8, false)]);
+    }
+
+    #[test]
+    fn validate_utf16_locations() {
+        let (empty_lang_db, _, sample_uri) = shared_sample_code();
+        // é is 2 bytes and 1 utf-16 unit, 😀 is 4 bytes and 2 utf-16 units
+        let code = "/* é */ int total;\nvoid g(char *s, int n) {}\nvoid main() {\n    int count = 1;\n    g(\"héllo😀\", count);\n    g(\"😀\", missing_name);\n}\n";
+        let ps = parser::parse(code.to_owned(), &sample_uri, &empty_lang_db);
+        let range =
+            |line, start, end| Range::new(Position::new(line, start), Position::new(line, end));
+
+        // rename, references and highlights
+        let position = Position::new(4, 17);
+        let (symbol, name_range) = prov_rename::prepare_rename(&ps, &sample_uri, position).unwrap();
+        assert_eq!(name_range, range(4, 17, 22));
+        let occurrences: Vec<Range> = symbols::find_occurrences(&ps, &sample_uri, &symbol)
+            .iter()
+            .map(|l| l.range)
+            .collect();
+        assert_eq!(occurrences, vec![range(3, 8, 13), range(4, 17, 22)]);
+        let highlights: Vec<Range> = prov_highlight::get_highlights(&ps, &sample_uri, position)
+            .unwrap()
+            .iter()
+            .map(|h| h.range)
+            .collect();
+        assert_eq!(highlights, occurrences);
+
+        // document symbols
+        let doc_symbols = prov_document_symbol::get_document_symbols(&ps, &sample_uri);
+        let total = doc_symbols.iter().find(|s| s.name == "total").unwrap();
+        assert_eq!(total.selection_range, range(0, 12, 17));
+
+        // diagnostics
+        let items = diagnostics::get_diagnostics(&ps, &sample_uri, "test");
+        let missing = items
+            .iter()
+            .find(|d| d.message.contains("missing_name"))
+            .unwrap();
+        assert_eq!(missing.range, range(5, 12, 24));
+    }
+
+    #[test]
+    fn validate_document_symbols() {
         let (empty_lang_db, sample_code, sample_uri) = shared_sample_code();
         let result = parser::parse(sample_code.to_owned(), &sample_uri, &empty_lang_db);
+        let symbols = prov_document_symbol::get_document_symbols(&result, &sample_uri);
+
+        // (name, kind, start line, end line, children)
+        fn summarize(
+            symbols: &[DocumentSymbol],
+        ) -> Vec<(String, SymbolKind, u32, u32, Vec<String>)> {
+            return symbols
+                .iter()
+                .map(|s| {
+                    (
+                        s.name.to_owned(),
+                        s.kind,
+                        s.range.start.line,
+                        s.range.end.line,
+                        s.children
+                            .iter()
+                            .flatten()
+                            .map(|c| c.name.to_owned())
+                            .collect(),
+                    )
+                })
+                .collect();
+        }
+        assert_eq!(
+            summarize(&symbols),
+            vec![
+                ("myRep".to_owned(), SymbolKind::CONSTANT, 2, 3, vec![]),
+                ("global_var".to_owned(), SymbolKind::VARIABLE, 4, 4, vec![]),
+                (
+                    "MyStruct".to_owned(),
+                    SymbolKind::STRUCT,
+                    6,
+                    9,
+                    vec!["myField".to_owned(), "arrayField".to_owned()]
+                ),
+                (
+                    "main".to_owned(),
+                    SymbolKind::FUNCTION,
+                    11,
+                    16,
+                    vec![
+                        "param_var".to_owned(),
+                        "init_var".to_owned(),
+                        "prim_var".to_owned(),
+                        "cust_var".to_owned(),
+                        "array_var".to_owned()
+                    ]
+                ),
+            ]
+        );
+
+        let main = &symbols[3];
+        assert_eq!(main.detail, Some("void main(vec2 param_var)".to_owned()));
+        assert_eq!(
+            main.selection_range,
+            location_of(sample_code, "main", &sample_uri).range
+        );
+        let array_var = &main.children.as_ref().unwrap()[4];
+        assert_eq!(array_var.detail, Some("float[][]".to_owned()));
+        assert_eq!(array_var.kind, SymbolKind::VARIABLE);
+    }
+
+    #[test]
+    fn validate_workspace_symbols() {
+        let (empty_lang_db, _, _) = shared_sample_code();
+        let lib_code = "struct Shape {\n    int sides;\n};\n#define MAX_SIDES 4\nint shape_area(Shape s) {\n    return 0;\n}\n";
+        let main_code = "int shapes_drawn;\nvoid main() {\n    int local_shape;\n}\n";
+        let lib_uri = Url::parse("file:///ws/lib.cstyle").unwrap();
+        let main_uri = Url::parse("file:///ws/main.cstyle").unwrap();
+
+        let mut index = workspace_index::WorkspaceIndex::default();
+        for (code, uri) in [(lib_code, &lib_uri), (main_code, &main_uri)] {
+            index.update(uri, &parser::parse(code.to_owned(), uri, &empty_lang_db), 0);
+        }
+
+        let names = |query: &str| {
+            return prov_workspace_symbol::get_workspace_symbols(&index, query)
+                .iter()
+                .map(|s| (s.name.to_owned(), s.kind))
+                .collect::<Vec<(String, SymbolKind)>>();
+        };
 
-        let fr = prov_folding::get_folding_ranges(&result.global_scope);
+        // exact and prefix matches first, locals are not workspace symbols
+        assert_eq!(
+            names("shape"),
+            vec![
+                ("Shape".to_owned(), SymbolKind::STRUCT),
+                ("shape_area".to_owned(), SymbolKind::FUNCTION),
+                ("shapes_drawn".to_owned(), SymbolKind::VARIABLE),
+            ]
+        );
+        // subsequences, preferring the start of words
+        assert_eq!(
+            names("sa"),
+            vec![
+                ("shape_area".to_owned(), SymbolKind::FUNCTION),
+                ("Shape".to_owned(), SymbolKind::STRUCT),
+                ("shapes_drawn".to_owned(), SymbolKind::VARIABLE),
+            ]
+        );
+        assert_eq!(
+            names("msd"),
+            vec![("MAX_SIDES".to_owned(), SymbolKind::CONSTANT)]
+        );
+        assert_eq!(names("xyz"), vec![]);
+        assert_eq!(names("").len(), 5);
+
+        // symbols with only a file are resolved to their declaration
+        let symbol = WorkspaceSymbol {
+            name: "MAX_SIDES".to_owned(),
+            kind: SymbolKind::CONSTANT,
+            tags: None,
+            container_name: None,
+            location: OneOf::Right(WorkspaceLocation {
+                uri: lib_uri.clone(),
+            }),
+            data: None,
+        };
+        let resolved = prov_workspace_symbol::resolve_workspace_symbol(&index, symbol);
+        assert_eq!(
+            resolved.location,
+            OneOf::Left(location_of(lib_code, "MAX_SIDES", &lib_uri))
+        );
+        assert_eq!(
+            prov_workspace_symbol::capabilities(),
+            OneOf::Right(WorkspaceSymbolOptions {
+                resolve_provider: Some(true),
+                work_done_progress_options: WorkDoneProgressOptions {
+                    work_done_progress: None,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn validate_completions() {}
+
+    #[test]
+    fn validate_folding() {
+        let (empty_lang_db, _, sample_uri) = shared_sample_code();
+        let src = r#"#include "a.h"
+#include "b.h"
+/* block
+   comment */
+// region helpers
+struct Pair {
+    int a;
+};
+// endregion
+// line one
+// line two
+#ifdef FAST
+int speed = 2;
+int accel = 1;
+#else
+int speed = 1;
+#endif
+int nums[2] = {
+    1, 2
+};
+void main() {
+    if (speed) {
+        nums[0] = 3;
+    }
+}
+"#;
+        let result = parser::parse(src.to_owned(), &sample_uri, &empty_lang_db);
+
+        let fr: Vec<(u32, u32, u32, u32, Option<FoldingRangeKind>)> =
+            prov_folding::get_folding_ranges(&result)
+                .into_iter()
+                .map(|fr| {
+                    (
+                        fr.start_line,
+                        fr.start_character.unwrap(),
+                        fr.end_line,
+                        fr.end_character.unwrap(),
+                        fr.kind,
+                    )
+                })
+                .collect();
+        assert_eq!(
+            fr,
+            vec![
+                (0, 0, 1, 14, Some(FoldingRangeKind::Imports)),
+                (2, 0, 3, 13, Some(FoldingRangeKind::Comment)),
+                (4, 17, 8, 12, Some(FoldingRangeKind::Region)),
+                (5, 13, 6, 10, None),
+                (9, 0, 10, 11, Some(FoldingRangeKind::Comment)),
+                (11, 11, 13, 14, Some(FoldingRangeKind::Region)),
+                (14, 5, 15, 14, Some(FoldingRangeKind::Region)),
+                (17, 15, 18, 8, None),
+                (20, 13, 23, 5, None),
+                (21, 16, 22, 20, None),
+            ]
+        );
+
+        // trailing comments don't start a run, and columns are in utf-16 units
+        // a `}` that doesn't start its line can't be hidden, so the fold ends right before it
+        let src = "int x; // a\n// b\n// c\n#ifdef A\nchar *s = \"éé\";\n#endif\nvoid f() {\n    f(); }\n";
+        let result = parser::parse(src.to_owned(), &sample_uri, &empty_lang_db);
+        let fr: Vec<(u32, u32, u32, u32, Option<FoldingRangeKind>)> =
+            prov_folding::get_folding_ranges(&result)
+                .into_iter()
+                .map(|fr| {
+                    (
+                        fr.start_line,
+                        fr.start_character.unwrap(),
+                        fr.end_line,
+                        fr.end_character.unwrap(),
+                        fr.kind,
+                    )
+                })
+                .collect();
         assert_eq!(
             fr,
-            vec![FoldingRange {
-                start_line: 11,
-                start_character: None,
-                end_line: 16,
-                end_character: None,
-                kind: Some(FoldingRangeKind::Region),
-                collapsed_text: None
-            }]
+            vec![
+                (1, 0, 2, 4, Some(FoldingRangeKind::Comment)),
+                (3, 8, 4, 15, Some(FoldingRangeKind::Region)),
+                (6, 10, 7, 9, None),
+            ]
         );
     }
 
@@ -447,5 +2291,6 @@ mod tests {
         prov_hover::capabilities();
         prov_completions::capabilities();
         prov_folding::capabilities();
+        prov_signature_help::capabilities();
     }
 }
@@ -2,23 +2,118 @@ use std::vec;
 use tower_lsp::lsp_types::*;
 use tree_sitter::{Node, Point};
 
-pub fn point_to_position(point: Point) -> Position {
+// LSP positions use utf-16 columns, tree-sitter points use byte columns
+
+// converts a tree-sitter point (byte column) at the byte offset into an LSP position (utf-16 column)
+pub fn point_to_utf16_position(text: &str, point: Point, byte: usize) -> Position {
+    let line_start = byte - point.column;
     return Position {
         line: point.row as u32,
-        character: point.column as u32,
+        character: text[line_start..byte].encode_utf16().count() as u32,
     };
 }
 
-pub fn node_to_location(node: Node, uri: &Url) -> Location {
+// the range of a node as LSP positions (utf-16 columns)
+pub fn node_to_utf16_range(text: &str, node: Node) -> Range {
+    return Range {
+        start: point_to_utf16_position(text, node.start_position(), node.start_byte()),
+        end: point_to_utf16_position(text, node.end_position(), node.end_byte()),
+    };
+}
+
+// the start of a node as an LSP position (utf-16 column)
+pub fn node_start(text: &str, node: Node) -> Position {
+    return point_to_utf16_position(text, node.start_position(), node.start_byte());
+}
+
+pub fn node_to_location(text: &str, node: Node, uri: &Url) -> Location {
     return Location {
         uri: uri.to_owned(),
-        range: Range {
-            start: point_to_position(node.start_position()),
-            end: point_to_position(node.end_position()),
-        },
+        range: node_to_utf16_range(text, node),
     };
 }
 
+// converts an LSP position (utf-16 column) into a byte offset into the text, clamped to the text bounds
+pub fn position_to_byte(text: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match text[line_start..].find('\n') {
+            Some(offset) => line_start += offset + 1,
+            None => return text.len(),
+        }
+    }
+
+    let line_end = match text[line_start..].find('\n') {
+        Some(offset) => line_start + offset,
+        None => text.len(),
+    };
+
+    return line_start + byte_column(&text[line_start..line_end], position.character);
+}
+
+// converts a byte offset into the text into a tree-sitter point (byte column)
+pub fn byte_to_point(text: &str, byte: usize) -> Point {
+    let before = &text.as_bytes()[..byte];
+    let row = before.iter().filter(|b| **b == b'\n').count();
+    let column = match before.iter().rposition(|b| *b == b'\n') {
+        Some(newline) => byte - newline - 1,
+        None => byte,
+    };
+    return Point { row, column };
+}
+
+// true if the object was declared in the document at uri (false for builtins)
+pub fn declared_in(declaration_position: &Option<Location>, uri: &Url) -> bool {
+    match declaration_position {
+        Some(loc) => loc.uri == *uri,
+        None => false,
+    }
+}
+
+// TODO lang specific - names that never produce unused warnings
+pub fn is_unused_exempt(name: &str) -> bool {
+    return name.starts_with('_');
+}
+
+// fields such as `declarator` can appear more than once (`int a, b;`)
+pub fn is_field(node: Node, parent: Node, field: &str) -> bool {
+    return parent
+        .children_by_field_name(field, &mut parent.walk())
+        .any(|child| child == node);
+}
+
+// identifiers that name something rather than refer to it
+pub fn is_declaration_site(node: Node) -> bool {
+    let Some(parent) = node.parent() else {
+        return false;
+    };
+    if is_field(node, parent, "declarator") {
+        return true;
+    }
+    match parent.kind() {
+        "enumerator"
+        | "preproc_def"
+        | "preproc_function_def"
+        | "struct_specifier"
+        | "union_specifier"
+        | "enum_specifier" => return is_field(node, parent, "name"),
+        "preproc_params" => return true,
+        _ => return false,
+    }
+}
+
+// the byte offset into the line of an LSP (utf-16) column
+fn byte_column(line: &str, character: u32) -> usize {
+    let mut utf16_count = 0;
+    for (offset, c) in line.char_indices() {
+        if utf16_count >= character as usize {
+            return offset;
+        }
+        utf16_count += c.len_utf16();
+    }
+    return line.len();
+}
+
 fn is_word_char(b: u8) -> bool {
     b.is_ascii_alphanumeric() || b == b'_'
 }
@@ -27,8 +122,8 @@ pub fn extract_word_at(text: &str, position: Position) -> String {
     let line = text.lines().nth(position.line as usize).unwrap_or("");
 
     let bytes = line.as_bytes();
-    let mut start = position.character as usize;
-    let mut end = position.character as usize;
+    let mut start = byte_column(line, position.character);
+    let mut end = start;
 
     // Move backward to find word start
     while start > 0 && is_word_char(bytes[start - 1]) {
@@ -55,7 +150,7 @@ fn extract_single_ident(line: &str, pos: &mut usize) -> String {
 
 fn extract_array_access(line: &str, pos: &mut usize) -> bool {
     let bytes = line.as_bytes();
-    if *pos <= 0 || bytes[*pos - 1] != b']' {
+    if *pos == 0 || bytes[*pos - 1] != b']' {
         return false;
     }
 
@@ -81,7 +176,7 @@ pub fn extract_identifier_sequence(text: &str, position: Position) -> Vec<String
     let mut idents = vec![];
 
     let bytes = line.as_bytes();
-    let mut start = position.character as usize;
+    let mut start = byte_column(line, position.character);
 
     extract_single_ident(line, &mut start); // removes the leading ident
     while start > 0 && bytes[start - 1] == b'.' {
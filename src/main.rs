@@ -1,37 +1,262 @@
+// the codebase consistently uses explicit returns and LSP style constant names
+#![allow(clippy::needless_return)]
+#![allow(clippy::upper_case_acronyms)]
+#![allow(clippy::four_forward_slashes)]
+#![allow(clippy::len_zero)]
+
 use std::collections::HashMap;
 use std::env;
-use std::fs::File;
-use std::io::BufReader;
+use std::path::PathBuf;
 
-use log::debug;
+use log::{debug, error};
 use tokio::sync::RwLock;
-use tower_lsp::jsonrpc::Result;
+use tower_lsp::jsonrpc::{Error, ErrorCode, Result};
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer, LspService, Server};
 
 mod diagnostics;
+mod includes;
+mod lang_db_watcher;
 mod lang_types;
 mod lsp_test; // makes tests accessible
 mod lsp_util;
 mod parser;
+mod profiles;
 mod prov_code_lens;
 mod prov_completions;
+mod prov_document_symbol;
 mod prov_folding;
 mod prov_goto;
+mod prov_highlight;
 mod prov_hover;
 mod prov_inlay_hint;
+mod prov_rename;
 mod prov_semantic_tokens;
 mod prov_signature_help;
+mod prov_workspace_symbol;
+mod symbols;
+mod type_inference;
+mod workspace_index;
+
+// TODO lang specific - environment variable that can point at the LangDB file
+const LANG_DB_ENV_VAR: &str = "CSTYLE_LANG_DB";
 
 struct Backend {
     client: Client,
-    lang_db: lang_types::LangDB,
+    // there is always at least one profile, the first is used for unmatched documents
+    profiles: RwLock<Vec<profiles::LangProfile>>,
+    // LangDB of the default profile, set from the command line or environment
+    lang_db_path: Option<PathBuf>,
     documents: RwLock<HashMap<Url, lang_types::ParseState>>,
+    // index into profiles for every open document
+    document_profiles: RwLock<HashMap<Url, usize>>,
+    workspace_folders: RwLock<Vec<PathBuf>>,
+    include_paths: RwLock<Vec<PathBuf>>,
+    workspace_index: RwLock<workspace_index::WorkspaceIndex>,
+    // last full semantic tokens sent for every open document (result id, tokens), used for deltas
+    semantic_tokens: RwLock<HashMap<Url, (u64, Vec<SemanticToken>)>>,
+}
+
+impl Backend {
+    // remembers the tokens sent for a document so the next delta request can diff against them
+    async fn store_sm_tokens(&self, uri: &Url, tokens: Vec<SemanticToken>) -> String {
+        let mut semantic_tokens = self.semantic_tokens.write().await;
+        let id = semantic_tokens.get(uri).map_or(0, |(id, _)| id + 1);
+        semantic_tokens.insert(uri.to_owned(), (id, tokens));
+        return id.to_string();
+    }
+
+    // an unusable LangDB is reported to the user and None is returned
+    async fn load_lang_db(&self, config: &profiles::ProfileConfig) -> Option<lang_types::LangDB> {
+        let result = match &config.lang_db_path {
+            Some(path) => lang_types::LangDB::load(path),
+            None => Err(
+                "Couldn't find lang_db.json, set it with --lang-db, ".to_owned()
+                    + LANG_DB_ENV_VAR
+                    + " or the langDbPath initialization option",
+            ),
+        };
+
+        match result {
+            Ok(lang_db) => return Some(lang_db),
+            Err(msg) => {
+                let msg = config.name.to_owned() + ": " + &msg;
+                error!("{}", msg);
+                self.client.show_message(MessageType::ERROR, msg).await;
+                return None;
+            }
+        }
+    }
+
+    // the profile of an open document, or the best match for a document that isn't open
+    async fn document_profile(&self, uri: &Url, profiles: &[profiles::LangProfile]) -> usize {
+        match self.document_profiles.read().await.get(uri) {
+            Some(idx) => return *idx,
+            None => return profiles::profile_for(profiles, uri, None),
+        }
+    }
+
+    // stores the document's own objects in the workspace index, then merges in objects from
+    // included files, followed by objects from the rest of the workspace
+    // returns true if the document's own objects changed, so other documents need to be re-merged
+    async fn index_and_merge(
+        &self,
+        uri: &Url,
+        parse_state: &mut lang_types::ParseState,
+        lang_db: &lang_types::LangDB,
+        profile: usize,
+    ) -> bool {
+        let mut index = self.workspace_index.write().await;
+        let changed = index.update(uri, parse_state, profile);
+        includes::merge_includes(
+            parse_state,
+            uri,
+            &mut index,
+            profile,
+            lang_db,
+            &self.include_paths.read().await,
+        );
+        index.merge_into(parse_state, uri, profile);
+        index.mark_merged(uri);
+        return changed;
+    }
+
+    // merges an open document again, its own objects haven't changed so the whole tree is reused
+    async fn remerge_document(
+        &self,
+        uri: &Url,
+        documents: &mut HashMap<Url, lang_types::ParseState>,
+        profiles: &[profiles::LangProfile],
+    ) {
+        let profile = self.document_profile(uri, profiles).await;
+        let lang_db = &profiles[profile].lang_db;
+        let Some(old_state) = documents.remove(uri) else {
+            return;
+        };
+        let mut parse_state = parser::parse_incremental(old_state, &[], uri, lang_db);
+        self.index_and_merge(uri, &mut parse_state, lang_db, profile)
+            .await;
+        documents.insert(uri.clone(), parse_state);
+    }
+
+    // re-merges open documents merged before declarations in other files moved, so the locations
+    // they hand out are current, their diagnostics don't depend on locations and aren't republished
+    async fn refresh_locations(&self) {
+        let profiles = self.profiles.read().await;
+        let mut documents = self.documents.write().await;
+        let outdated: Vec<Url> = {
+            let index = self.workspace_index.read().await;
+            documents
+                .keys()
+                .filter(|uri| index.is_outdated(uri))
+                .cloned()
+                .collect()
+        };
+        for uri in outdated {
+            self.remerge_document(&uri, &mut documents, &profiles).await;
+        }
+    }
+
+    // re-merges the open documents of the profiles after the workspace index changed, so they see
+    // objects added, changed or removed in other files, then republishes their diagnostics
+    // skip is the document that caused the change, which is already merged
+    async fn remerge_documents(&self, changed_profiles: &[usize], skip: Option<&Url>) {
+        let mut remerged = vec![];
+        {
+            let profiles = self.profiles.read().await;
+            let mut documents = self.documents.write().await;
+            let uris: Vec<Url> = documents.keys().cloned().collect();
+            for uri in uris {
+                let profile = self.document_profile(&uri, &profiles).await;
+                if Some(&uri) == skip || !changed_profiles.contains(&profile) {
+                    continue;
+                }
+                self.remerge_document(&uri, &mut documents, &profiles).await;
+                remerged.push(uri);
+            }
+        }
+
+        for uri in remerged {
+            self.generate_diagnostics(uri, None).await;
+        }
+    }
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        let mut folders = vec![];
+        for folder in params.workspace_folders.unwrap_or_default() {
+            if let Ok(path) = folder.uri.to_file_path() {
+                folders.push(path);
+            }
+        }
+        #[allow(deprecated)] // root_uri is still sent by clients without workspace folder support
+        if folders.is_empty() {
+            if let Some(Ok(path)) = params.root_uri.map(|uri| uri.to_file_path()) {
+                folders.push(path);
+            }
+        }
+
+        // relative paths in the options are resolved against the first workspace folder
+        let resolve_path = |path: &str| match folders.first() {
+            Some(folder) => folder.join(path),
+            None => PathBuf::from(path),
+        };
+        let options = params.initialization_options.unwrap_or_default();
+
+        let mut include_paths = vec![];
+        if let Some(paths) = options.get("includePaths").and_then(|v| v.as_array()) {
+            for path in paths.iter().filter_map(|v| v.as_str()) {
+                include_paths.push(resolve_path(path));
+            }
+        }
+        *self.include_paths.write().await = include_paths;
+
+        let mut configs: Vec<profiles::ProfileConfig> = vec![];
+        if let Some(value) = options.get("profiles") {
+            match serde_json::from_value(value.clone()) {
+                Ok(parsed) => configs = parsed,
+                Err(e) => {
+                    let msg = format!("Invalid profiles initialization option: {}", e);
+                    error!("{}", msg);
+                    self.client.show_message(MessageType::ERROR, msg).await;
+                }
+            }
+        }
+        for config in configs.iter_mut() {
+            config.lang_db_path = config
+                .lang_db_path
+                .as_ref()
+                .map(|path| resolve_path(&path.to_string_lossy()));
+        }
+
+        if configs.is_empty() {
+            // command line / environment > initialization options > next to the repo root
+            let lang_db_path = self
+                .lang_db_path
+                .clone()
+                .or_else(|| {
+                    options
+                        .get("langDbPath")
+                        .and_then(|v| v.as_str())
+                        .map(resolve_path)
+                })
+                .or_else(default_lang_db_path);
+            configs.push(profiles::ProfileConfig::default_profile(lang_db_path));
+        }
+        for config in configs.iter_mut() {
+            config.lang_db_path = config.lang_db_path.as_deref().map(profiles::absolute_path);
+        }
+
+        let mut loaded = vec![];
+        for config in configs {
+            let lang_db = self.load_lang_db(&config).await.unwrap_or_default();
+            loaded.push(profiles::LangProfile { config, lang_db });
+        }
+        *self.profiles.write().await = loaded;
+        *self.workspace_folders.write().await = folders;
+
         Ok(InitializeResult {
             capabilities: ServerCapabilities {
                 completion_provider: Some(prov_completions::capabilities()),
@@ -43,12 +268,17 @@ impl LanguageServer for Backend {
                 inlay_hint_provider: Some(prov_inlay_hint::capabilities()),
                 signature_help_provider: Some(prov_signature_help::capabilities()),
                 code_lens_provider: Some(prov_code_lens::capabilities()),
+                rename_provider: Some(prov_rename::capabilities()),
+                document_highlight_provider: Some(prov_highlight::capabilities()),
+                document_symbol_provider: Some(prov_document_symbol::capabilities()),
+                workspace_symbol_provider: Some(prov_workspace_symbol::capabilities()),
 
-                //folding_range_provider: Some(prov_folding::capabilities()), // the default indentation based is better
+                folding_range_provider: Some(prov_folding::capabilities()),
                 text_document_sync: Some(TextDocumentSyncCapability::Options(
                     TextDocumentSyncOptions {
                         open_close: Some(true),
-                        change: Some(TextDocumentSyncKind::FULL),
+                        change: Some(TextDocumentSyncKind::INCREMENTAL),
+                        save: Some(TextDocumentSyncSaveOptions::Supported(true)),
                         ..Default::default()
                     },
                 )),
@@ -60,6 +290,41 @@ impl LanguageServer for Backend {
 
     async fn initialized(&self, _: InitializedParams) {
         debug!("initialized!");
+
+        let profiles = self.profiles.read().await;
+        let mut index = self.workspace_index.write().await;
+        for folder in self.workspace_folders.read().await.iter() {
+            index.scan_folder(folder, &profiles);
+        }
+        debug!("indexed {} workspace files", index.files.len());
+        drop(index);
+        drop(profiles);
+
+        self.register_lang_db_watcher().await;
+        self.register_workspace_watcher().await;
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        let changed_profiles: Vec<usize> = {
+            let profiles = self.profiles.read().await;
+            (0..profiles.len())
+                .filter(|idx| {
+                    let Some(lang_db_path) = &profiles[*idx].config.lang_db_path else {
+                        return false;
+                    };
+                    params.changes.iter().any(|change| {
+                        change
+                            .uri
+                            .to_file_path()
+                            .is_ok_and(|path| profiles::absolute_path(&path) == *lang_db_path)
+                    })
+                })
+                .collect()
+        };
+        for idx in changed_profiles {
+            self.reload_lang_db(idx).await;
+        }
+        self.reindex_changed_files(&params.changes).await;
     }
 
     async fn shutdown(&self) -> Result<()> {
@@ -85,44 +350,132 @@ impl LanguageServer for Backend {
 
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
         debug!("file opened");
-        self.documents.write().await.insert(
-            params.text_document.uri.clone(),
-            parser::parse(
+        let (profile, changed) = {
+            let profiles = self.profiles.read().await;
+            let profile = profiles::profile_for(
+                &profiles,
+                &params.text_document.uri,
+                Some(&params.text_document.language_id),
+            );
+            self.document_profiles
+                .write()
+                .await
+                .insert(params.text_document.uri.clone(), profile);
+
+            let lang_db = &profiles[profile].lang_db;
+            let mut parse_state = parser::parse(
                 params.text_document.text.clone(),
                 &params.text_document.uri,
-                &self.lang_db,
-            ),
-        );
+                lang_db,
+            );
+            let changed = self
+                .index_and_merge(
+                    &params.text_document.uri,
+                    &mut parse_state,
+                    lang_db,
+                    profile,
+                )
+                .await;
+            self.documents
+                .write()
+                .await
+                .insert(params.text_document.uri.clone(), parse_state);
+            (profile, changed)
+        };
 
         self.generate_diagnostics(
-            &params.text_document.text,
-            params.text_document.uri,
-            params.text_document.version,
+            params.text_document.uri.clone(),
+            Some(params.text_document.version),
         )
         .await;
+        if changed {
+            self.remerge_documents(&[profile], Some(&params.text_document.uri))
+                .await;
+        }
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
-        self.documents.write().await.insert(
-            params.text_document.uri.clone(),
-            parser::parse(
-                params.content_changes[0].text.clone(),
+        let (profile, changed) = {
+            let profiles = self.profiles.read().await;
+            let profile = self
+                .document_profile(&params.text_document.uri, &profiles)
+                .await;
+            let lang_db = &profiles[profile].lang_db;
+            let mut rw_guard = self.documents.write().await;
+            let old_state = match rw_guard.remove(&params.text_document.uri) {
+                Some(old_state) => old_state,
+                None => parser::parse(String::new(), &params.text_document.uri, lang_db),
+            };
+            let mut parse_state = parser::parse_incremental(
+                old_state,
+                &params.content_changes,
                 &params.text_document.uri,
-                &self.lang_db,
-            ),
-        );
+                lang_db,
+            );
+            let changed = self
+                .index_and_merge(
+                    &params.text_document.uri,
+                    &mut parse_state,
+                    lang_db,
+                    profile,
+                )
+                .await;
+            rw_guard.insert(params.text_document.uri.clone(), parse_state);
+            (profile, changed)
+        };
 
         self.generate_diagnostics(
-            &params.content_changes[0].text, // 0 b/c TextDocumentSyncKind::FULL
-            params.text_document.uri,
-            params.text_document.version,
+            params.text_document.uri.clone(),
+            Some(params.text_document.version),
         )
         .await;
+        if changed {
+            self.remerge_documents(&[profile], Some(&params.text_document.uri))
+                .await;
+        }
+    }
+
+    async fn did_save(&self, params: DidSaveTextDocumentParams) {
+        // the open document is indexed from its text, a copy read from disk for an include is outdated
+        self.workspace_index
+            .write()
+            .await
+            .invalidate(&params.text_document.uri);
     }
 
     async fn did_close(&self, params: DidCloseTextDocumentParams) {
-        self.generate_diagnostics("", params.text_document.uri, 0)
+        // unsaved changes are discarded on close, so go back to the file on disk
+        let profiles = self.profiles.read().await;
+        let profile = self
+            .document_profile(&params.text_document.uri, &profiles)
+            .await;
+        let mut index = self.workspace_index.write().await;
+        let on_disk = match params.text_document.uri.to_file_path() {
+            Ok(path) => index.index_file(&path, profile, &profiles[profile].lang_db),
+            Err(_) => false,
+        };
+        if !on_disk {
+            index.files.remove(&params.text_document.uri);
+        }
+        index.forget_merged(&params.text_document.uri);
+        drop(index);
+        drop(profiles);
+
+        self.clear_diagnostics(params.text_document.uri.clone())
             .await;
+        self.documents
+            .write()
+            .await
+            .remove(&params.text_document.uri);
+        self.document_profiles
+            .write()
+            .await
+            .remove(&params.text_document.uri);
+        self.semantic_tokens
+            .write()
+            .await
+            .remove(&params.text_document.uri);
+        self.remerge_documents(&[profile], None).await;
     }
 
     async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
@@ -140,29 +493,204 @@ impl LanguageServer for Backend {
         }
     }
 
-    async fn folding_range(&self, params: FoldingRangeParams) -> Result<Option<Vec<FoldingRange>>> {
+    async fn signature_help(&self, params: SignatureHelpParams) -> Result<Option<SignatureHelp>> {
+        let rw_guard = self.documents.read().await;
+        let parse_state = rw_guard.get(&params.text_document_position_params.text_document.uri);
+        match parse_state {
+            Some(parse_state) => Ok(prov_signature_help::get_signature_help(
+                &lang_types::get_scoped_parse_state(
+                    parse_state,
+                    params.text_document_position_params.position,
+                ),
+                params.text_document_position_params.position,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    async fn document_highlight(
+        &self,
+        params: DocumentHighlightParams,
+    ) -> Result<Option<Vec<DocumentHighlight>>> {
+        let uri = &params.text_document_position_params.text_document.uri;
+        let rw_guard = self.documents.read().await;
+        let parse_state = rw_guard.get(uri);
+
+        match parse_state {
+            Some(parse_state) => Ok(prov_highlight::get_highlights(
+                parse_state,
+                uri,
+                params.text_document_position_params.position,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> Result<Option<DocumentSymbolResponse>> {
         let rw_guard = self.documents.read().await;
         let parse_state = rw_guard.get(&params.text_document.uri);
 
         match parse_state {
-            Some(parse_state) => Ok(Some(prov_folding::get_folding_ranges(
-                &parse_state.global_scope,
+            Some(parse_state) => Ok(Some(DocumentSymbolResponse::Nested(
+                prov_document_symbol::get_document_symbols(parse_state, &params.text_document.uri),
             ))),
             None => Ok(None),
         }
     }
 
+    // tower-lsp answers workspace/symbol with SymbolInformation, so locations are always filled in
+    // resolve is still supported for clients holding symbols that only have a file
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> Result<Option<Vec<SymbolInformation>>> {
+        let index = self.workspace_index.read().await;
+        return Ok(Some(prov_workspace_symbol::get_workspace_symbols(
+            &index,
+            &params.query,
+        )));
+    }
+
+    async fn symbol_resolve(&self, params: WorkspaceSymbol) -> Result<WorkspaceSymbol> {
+        let index = self.workspace_index.read().await;
+        return Ok(prov_workspace_symbol::resolve_workspace_symbol(
+            &index, params,
+        ));
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<PrepareRenameResponse>> {
+        let rw_guard = self.documents.read().await;
+        let Some(parse_state) = rw_guard.get(&params.text_document.uri) else {
+            return Ok(None);
+        };
+
+        let (symbol, range) =
+            prov_rename::prepare_rename(parse_state, &params.text_document.uri, params.position)
+                .map_err(request_failed)?;
+        return Ok(Some(PrepareRenameResponse::RangeWithPlaceholder {
+            range,
+            placeholder: symbol.name().to_owned(),
+        }));
+    }
+
+    async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+        self.refresh_locations().await;
+        let uri = params.text_document_position.text_document.uri;
+        let profiles = self.profiles.read().await;
+        let documents = self.documents.read().await;
+        let Some(parse_state) = documents.get(&uri) else {
+            return Ok(None);
+        };
+
+        let (symbol, _) =
+            prov_rename::prepare_rename(parse_state, &uri, params.text_document_position.position)
+                .map_err(request_failed)?;
+        let locations = self
+            .find_workspace_occurrences(&uri, &symbol, &profiles, &documents)
+            .await;
+
+        // collisions are checked where the scopes are known, in this document
+        let local: Vec<Location> = locations.iter().filter(|l| l.uri == uri).cloned().collect();
+        prov_rename::check_new_name(parse_state, &symbol, &params.new_name, &local)
+            .map_err(request_failed)?;
+
+        return Ok(Some(prov_rename::rename_edits(locations, &params.new_name)));
+    }
+
+    async fn folding_range(&self, params: FoldingRangeParams) -> Result<Option<Vec<FoldingRange>>> {
+        let rw_guard = self.documents.read().await;
+        let parse_state = rw_guard.get(&params.text_document.uri);
+
+        match parse_state {
+            Some(parse_state) => Ok(Some(prov_folding::get_folding_ranges(parse_state))),
+            None => Ok(None),
+        }
+    }
+
     async fn semantic_tokens_full(
         &self,
         params: SemanticTokensParams,
     ) -> Result<Option<SemanticTokensResult>> {
+        let rw_guard = self.documents.read().await;
+        let Some(parse_state) = rw_guard.get(&params.text_document.uri) else {
+            return Ok(None);
+        };
+
+        let data =
+            prov_semantic_tokens::get_sm_tokens(parse_state, &params.text_document.uri, None);
+        let result_id = self
+            .store_sm_tokens(&params.text_document.uri, data.clone())
+            .await;
+        return Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
+            result_id: Some(result_id),
+            data,
+        })));
+    }
+
+    async fn semantic_tokens_full_delta(
+        &self,
+        params: SemanticTokensDeltaParams,
+    ) -> Result<Option<SemanticTokensFullDeltaResult>> {
+        let rw_guard = self.documents.read().await;
+        let Some(parse_state) = rw_guard.get(&params.text_document.uri) else {
+            return Ok(None);
+        };
+
+        let data =
+            prov_semantic_tokens::get_sm_tokens(parse_state, &params.text_document.uri, None);
+        let previous = self
+            .semantic_tokens
+            .read()
+            .await
+            .get(&params.text_document.uri)
+            .filter(|(id, _)| id.to_string() == params.previous_result_id)
+            .map(|(_, tokens)| prov_semantic_tokens::get_sm_token_edits(tokens, &data));
+        let result_id = self
+            .store_sm_tokens(&params.text_document.uri, data.clone())
+            .await;
+
+        // the client's result is unknown (or outdated), so send every token
+        match previous {
+            Some(edits) => {
+                return Ok(Some(SemanticTokensFullDeltaResult::TokensDelta(
+                    SemanticTokensDelta {
+                        result_id: Some(result_id),
+                        edits,
+                    },
+                )))
+            }
+            None => {
+                return Ok(Some(SemanticTokensFullDeltaResult::Tokens(
+                    SemanticTokens {
+                        result_id: Some(result_id),
+                        data,
+                    },
+                )))
+            }
+        }
+    }
+
+    async fn semantic_tokens_range(
+        &self,
+        params: SemanticTokensRangeParams,
+    ) -> Result<Option<SemanticTokensRangeResult>> {
         let rw_guard = self.documents.read().await;
         let parse_state = rw_guard.get(&params.text_document.uri);
 
         match parse_state {
-            Some(parse_state) => Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
+            Some(parse_state) => Ok(Some(SemanticTokensRangeResult::Tokens(SemanticTokens {
                 result_id: None,
-                data: prov_semantic_tokens::get_sm_tokens(parse_state),
+                data: prov_semantic_tokens::get_sm_tokens(
+                    parse_state,
+                    &params.text_document.uri,
+                    Some(params.range),
+                ),
             }))),
             None => Ok(None),
         }
@@ -172,6 +700,7 @@ impl LanguageServer for Backend {
         &self,
         params: GotoDefinitionParams,
     ) -> Result<Option<GotoDefinitionResponse>> {
+        self.refresh_locations().await;
         let rw_guard = self.documents.read().await;
         let parse_state = rw_guard.get(&params.text_document_position_params.text_document.uri);
 
@@ -191,6 +720,7 @@ impl LanguageServer for Backend {
         &self,
         params: GotoDefinitionParams,
     ) -> Result<Option<GotoDefinitionResponse>> {
+        self.refresh_locations().await;
         let rw_guard = self.documents.read().await;
         let parse_state = rw_guard.get(&params.text_document_position_params.text_document.uri);
 
@@ -207,20 +737,28 @@ impl LanguageServer for Backend {
     }
 
     async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
-        let rw_guard = self.documents.read().await;
-        let parse_state = rw_guard.get(&params.text_document_position.text_document.uri);
-        debug!("{:#?}", params);
-
-        match parse_state {
-            Some(parse_state) => Ok(prov_goto::goto_references(
-                &lang_types::get_scoped_parse_state(
-                    parse_state,
-                    params.text_document_position.position,
-                ),
-                params.text_document_position.position,
-            )),
-            None => Ok(None),
-        }
+        self.refresh_locations().await;
+        let uri = params.text_document_position.text_document.uri;
+        let profiles = self.profiles.read().await;
+        let documents = self.documents.read().await;
+        let Some(parse_state) = documents.get(&uri) else {
+            return Ok(None);
+        };
+        let Some((symbol, _)) =
+            symbols::symbol_at(parse_state, params.text_document_position.position)
+        else {
+            return Ok(None);
+        };
+
+        let occurrences = self
+            .find_workspace_occurrences(&uri, &symbol, &profiles, &documents)
+            .await;
+        return Ok(Some(prov_goto::goto_references(
+            parse_state,
+            &symbol,
+            occurrences,
+            params.context.include_declaration,
+        )));
     }
 
     async fn inlay_hint(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
@@ -234,6 +772,7 @@ impl LanguageServer for Backend {
     }
 
     async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+        self.refresh_locations().await;
         let rw_guard = self.documents.read().await;
         let parse_state = rw_guard.get(&params.text_document.uri);
 
@@ -247,34 +786,60 @@ impl LanguageServer for Backend {
     }
 }
 
-#[tokio::main]
-async fn main() {
-    env_logger::init();
+// shown to the user by the client, for requests that can't be completed (such as an invalid rename)
+fn request_failed(msg: String) -> Error {
+    return Error {
+        code: ErrorCode::ServerError(-32803), // RequestFailed
+        message: msg.into(),
+        data: None,
+    };
+}
 
-    // TODO - fix this path
-    let json_path = env::current_exe()
-        .unwrap()
-        .parent()
-        .unwrap()
-        .parent()
-        .unwrap()
-        .parent()
-        .unwrap()
-        .join("lang_db.json");
+// the development layout, target/<profile>/<exe> -> repo root
+fn default_lang_db_path() -> Option<PathBuf> {
+    let exe = env::current_exe().ok()?;
+    return Some(exe.parent()?.parent()?.parent()?.join("lang_db.json"));
+}
 
-    let file = File::open(json_path).unwrap();
-    let reader = BufReader::new(file);
+// `--lang-db <path>` or `--lang-db=<path>`, falling back to the environment variable
+// relative paths are resolved against the working directory
+fn lang_db_path_from_args() -> Option<PathBuf> {
+    let mut args = env::args().skip(1);
+    let mut path = None;
+    while let Some(arg) = args.next() {
+        if arg == "--lang-db" {
+            path = args.next().map(PathBuf::from);
+            break;
+        }
+        if let Some(arg_path) = arg.strip_prefix("--lang-db=") {
+            path = Some(PathBuf::from(arg_path));
+            break;
+        }
+    }
+    let path = path.or_else(|| env::var_os(LANG_DB_ENV_VAR).map(PathBuf::from))?;
+    return Some(profiles::absolute_path(&path));
+}
 
-    // Parse the JSON
-    let lang_db: lang_types::LangDB = serde_json::from_reader(reader).unwrap();
+#[tokio::main]
+async fn main() {
+    env_logger::init();
 
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
     let (service, socket) = LspService::build(|client| Backend {
         client,
-        lang_db,
+        profiles: RwLock::new(vec![profiles::LangProfile {
+            config: profiles::ProfileConfig::default_profile(None),
+            lang_db: lang_types::LangDB::default(),
+        }]),
+        lang_db_path: lang_db_path_from_args(),
         documents: RwLock::new(HashMap::new()),
+        document_profiles: RwLock::new(HashMap::new()),
+        workspace_folders: RwLock::new(vec![]),
+        include_paths: RwLock::new(vec![]),
+        workspace_index: RwLock::new(workspace_index::WorkspaceIndex::default()),
+        semantic_tokens: RwLock::new(HashMap::new()),
     })
     .finish();
 
@@ -1,9 +1,8 @@
 use crate::lang_types::*;
-use crate::lsp_util::node_to_location;
+use crate::lsp_util::{byte_to_point, node_to_location, node_to_utf16_range, position_to_byte};
 use std::collections::HashMap;
 use tower_lsp::lsp_types::*;
-use tree_sitter::{Node, Parser};
-use tree_sitter_c;
+use tree_sitter::{InputEdit, Node, Parser, Tree};
 
 fn process_struct(src: &str, node: Node, uri: &Url) -> Result<(String, LangType), &'static str> {
     let struct_name_node = node
@@ -16,8 +15,8 @@ fn process_struct(src: &str, node: Node, uri: &Url) -> Result<(String, LangType)
 
     let mut fields = HashMap::new();
     for field_declaration in body_node.children(&mut body_node.walk()) {
-        if let Ok((k, v)) = process_declaration(src, field_declaration, uri) {
-            fields.insert(k, v);
+        if let Ok(vars) = process_declaration(src, field_declaration, uri) {
+            fields.extend(vars);
         }
     }
 
@@ -25,85 +24,133 @@ fn process_struct(src: &str, node: Node, uri: &Url) -> Result<(String, LangType)
         struct_name.to_string(),
         LangType {
             fields,
-            declaration_position: Some(node_to_location(struct_name_node, uri)),
+            declaration_position: Some(node_to_location(src, struct_name_node, uri)),
             desc: "struct".to_string(),
             builtin: false,
         },
     ));
 }
 
+// qualifiers are pushed outermost first, so the last one is removed first by indexing or dereferencing
+// for example, `int *arr[2]` (an array of pointers) -> ['*', '[]']
 fn process_declarator(
     src: &str,
     node: Node,
     uri: &Url,
     type_list: &mut Vec<String>,
 ) -> Result<(String, Location), &'static str> {
-    let identifier;
-
-    match node.kind() {
-        "identifier" | "field_identifier" => {
-            identifier = (
-                node.utf8_text(src.as_bytes()).unwrap().to_string(),
-                node_to_location(node, uri),
-            )
-        }
+    let identifier = match node.kind() {
+        "identifier" | "field_identifier" => (
+            node.utf8_text(src.as_bytes()).unwrap().to_string(),
+            node_to_location(src, node, uri),
+        ),
         "array_declarator" => {
             let declarator_node = node
                 .child_by_field_name("declarator")
                 .ok_or("missing array_declarator declarator")?;
-            identifier = process_declarator(src, declarator_node, uri, type_list)?;
             type_list.push("[]".to_owned());
+            process_declarator(src, declarator_node, uri, type_list)?
+        }
+        "pointer_declarator" => {
+            let declarator_node = node
+                .child_by_field_name("declarator")
+                .ok_or("missing pointer_declarator declarator")?;
+            type_list.push("*".to_owned());
+            // `*const p` - qualifiers of the pointer itself
+            push_qualifiers(src, node, type_list);
+            process_declarator(src, declarator_node, uri, type_list)?
+        }
+        "function_declarator" => {
+            let declarator_node = node
+                .child_by_field_name("declarator")
+                .ok_or("missing function_declarator declarator")?;
+            // `int f(int x);` is a prototype, `int (*f)(int x);` is a function pointer
+            if declarator_node.kind() != "parenthesized_declarator" {
+                return Err("function prototype");
+            }
+            type_list.push("()".to_owned());
+            process_declarator(src, declarator_node, uri, type_list)?
+        }
+        "parenthesized_declarator" => {
+            let declarator_node = node
+                .named_child(0)
+                .ok_or("missing parenthesized_declarator declarator")?;
+            process_declarator(src, declarator_node, uri, type_list)?
         }
         "init_declarator" => {
             let declarator_node = node
                 .child_by_field_name("declarator")
                 .ok_or("missing array_declarator declarator")?;
-            identifier = process_declarator(src, declarator_node, uri, type_list)?;
+            process_declarator(src, declarator_node, uri, type_list)?
         }
         _ => return Err("unexpected node kind"),
-    }
+    };
 
     return Ok(identifier);
 }
 
-// TODO - multiple declarations?
-// TODO - type qualifiers?
-// can process an input like "double x = 5; vec3 x; vec4 x[2]; in body, array, or function header"
+// storage classes (`static`) and type qualifiers (`const`) that are direct children of node
+fn push_qualifiers(src: &str, node: Node, type_list: &mut Vec<String>) {
+    for child in node.children(&mut node.walk()) {
+        if child.kind() == "storage_class_specifier" || child.kind() == "type_qualifier" {
+            type_list.push(child.utf8_text(src.as_bytes()).unwrap().to_owned());
+        }
+    }
+}
+
+// can process an input like "static const double x = 5; vec3 x, *y; vec4 x[2];" in body, array, or function header
+// every declarator is returned, declarators that can't be processed (such as prototypes) are skipped
 fn process_declaration(
     src: &str,
     node: Node,
     uri: &Url,
-) -> Result<(String, LangVar), &'static str> {
-    let declarator_node = node
-        .child_by_field_name("declarator")
-        .ok_or("missing declaration declarator")?;
+) -> Result<Vec<(String, LangVar)>, &'static str> {
     let type_node = node
         .child_by_field_name("type")
         .ok_or("missing declaration type")?;
-
     let primary_type = type_node.utf8_text(src.as_bytes()).unwrap().to_string();
-    let mut type_qualifier_list = vec![];
-    let (identifier, location) =
-        process_declarator(src, declarator_node, uri, &mut type_qualifier_list)?;
+    let mut declaration_qualifiers = vec![];
+    push_qualifiers(src, node, &mut declaration_qualifiers);
 
-    return Ok((
-        identifier.to_string(),
-        LangVar {
-            primary_type,
-            type_qualifier_list,
-            declaration_position: Some(location),
-            unused: true,
-        },
-    ));
+    let mut vars = vec![];
+    for declarator_node in node.children_by_field_name("declarator", &mut node.walk()) {
+        let mut type_qualifier_list = declaration_qualifiers.clone();
+        if let Ok((identifier, location)) =
+            process_declarator(src, declarator_node, uri, &mut type_qualifier_list)
+        {
+            vars.push((
+                identifier,
+                LangVar {
+                    primary_type: primary_type.to_owned(),
+                    type_qualifier_list,
+                    declaration_position: Some(location),
+                    unused: true,
+                },
+            ));
+        }
+    }
+    if vars.is_empty() {
+        return Err("missing declaration declarator");
+    }
+
+    return Ok(vars);
 }
 
 fn process_function(src: &str, node: Node, uri: &Url) -> Result<(String, LangFunc), &'static str> {
     let type_node = node
         .child_by_field_name("type")
         .ok_or("missing function type")?;
-    let declarator_node = node
+    let mut declarator_node = node
         .child_by_field_name("declarator")
         .ok_or("missing function declarator")?;
+    // `int *f()` returns `int*`
+    let mut return_type = type_node.utf8_text(src.as_bytes()).unwrap().to_owned();
+    while declarator_node.kind() == "pointer_declarator" {
+        return_type += "*";
+        declarator_node = declarator_node
+            .child_by_field_name("declarator")
+            .ok_or("missing function declarator")?;
+    }
     let ident_node = declarator_node
         .child_by_field_name("declarator")
         .ok_or("missing function declarator ident")?;
@@ -112,12 +159,14 @@ fn process_function(src: &str, node: Node, uri: &Url) -> Result<(String, LangFun
         .ok_or("missing function parameters")?;
 
     let identifier = ident_node.utf8_text(src.as_bytes()).unwrap().to_owned();
-    let return_type = type_node.utf8_text(src.as_bytes()).unwrap().to_owned();
 
     let mut params = vec![];
+    let mut variadic = false;
     for param_declaration in params_node.children(&mut params_node.walk()) {
-        if let Ok((k, v)) = process_declaration(src, param_declaration, uri) {
-            params.push((k, v));
+        if param_declaration.kind() == "variadic_parameter" {
+            variadic = true;
+        } else if let Ok(vars) = process_declaration(src, param_declaration, uri) {
+            params.extend(vars);
         }
     }
 
@@ -126,7 +175,9 @@ fn process_function(src: &str, node: Node, uri: &Url) -> Result<(String, LangFun
         LangFunc {
             params,
             return_type,
-            declaration_position: Some(node_to_location(ident_node, uri)),
+            variadic,
+            deprecated: false,
+            declaration_position: Some(node_to_location(src, ident_node, uri)),
             references: vec![],
             desc: "".to_owned(), // TODO - grab surrounding comments for desc
         },
@@ -137,22 +188,85 @@ fn process_define(src: &str, node: Node, uri: &Url) -> Result<(String, LangDefin
     let name_node = node
         .child_by_field_name("name")
         .ok_or("missing define name")?;
-    let value_node = node
-        .child_by_field_name("value")
-        .ok_or("missing define value")?;
-
     let identifier = name_node.utf8_text(src.as_bytes()).unwrap().to_owned();
-    let insert_text = value_node.utf8_text(src.as_bytes()).unwrap().to_owned();
+
+    // `#define FLAG` has no value
+    let insert_text = match node.child_by_field_name("value") {
+        Some(value_node) => value_node
+            .utf8_text(src.as_bytes())
+            .unwrap()
+            .trim()
+            .to_owned(),
+        None => "".to_owned(),
+    };
 
     return Ok((
         identifier,
         LangDefine {
             insert_text,
-            declaration_position: Some(node_to_location(name_node, uri)),
+            declaration_position: Some(node_to_location(src, name_node, uri)),
         },
     ));
 }
 
+fn process_include(src: &str, node: Node, uri: &Url) -> Result<LangInclude, &'static str> {
+    let path_node = node
+        .child_by_field_name("path")
+        .ok_or("missing include path")?;
+
+    let system = match path_node.kind() {
+        "string_literal" => false,
+        "system_lib_string" => true,
+        _ => return Err("unsupported include path"),
+    };
+
+    // strip the surrounding quotes or angle brackets
+    let path_text = path_node.utf8_text(src.as_bytes()).unwrap();
+    if path_text.len() < 2 {
+        return Err("empty include path");
+    }
+
+    return Ok(LangInclude {
+        path: path_text[1..path_text.len() - 1].to_owned(),
+        system,
+        declaration_position: node_to_location(src, path_node, uri),
+    });
+}
+
+// functions (which hold their params), blocks, and `for` loops (which hold the init declaration)
+// the body of a function is part of the function's scope
+fn opens_scope(node: Node) -> bool {
+    match node.kind() {
+        "function_definition" | "for_statement" => return true,
+        "compound_statement" => {
+            return node
+                .parent()
+                .is_none_or(|parent| parent.kind() != "function_definition")
+        }
+        _ => return false,
+    }
+}
+
+// parameters of prototypes and function pointers (`int (*cb)(int v);`) only name the parameter
+// only the parameters of a function definition are vars in its scope
+fn is_definition_param(node: Node) -> bool {
+    let Some(mut declarator) = node.parent().and_then(|list| list.parent()) else {
+        return false;
+    };
+    // `int *f(int x) {}` returns a pointer
+    while let Some(parent) = declarator.parent() {
+        if parent.kind() != "pointer_declarator" {
+            break;
+        }
+        declarator = parent;
+    }
+    return declarator.parent().is_some_and(|parent| {
+        parent.kind() == "function_definition"
+            && parent.child_by_field_name("declarator") == Some(declarator)
+    });
+}
+
+#[allow(clippy::too_many_arguments)] // one accumulator per output
 fn extract_recursively(
     src: &str,
     node: Node,
@@ -160,11 +274,14 @@ fn extract_recursively(
     types: &mut HashMap<String, LangType>,
     functions: &mut HashMap<String, LangFunc>,
     defines: &mut HashMap<String, LangDefine>,
+    includes: &mut Vec<LangInclude>,
     active_scope: &mut Scope,
 ) {
-    if node.kind() == "declaration" || node.kind() == "parameter_declaration" {
-        if let Ok((name, lv)) = process_declaration(src, node, uri) {
-            active_scope.vars.insert(name, lv);
+    if node.kind() == "declaration"
+        || (node.kind() == "parameter_declaration" && is_definition_param(node))
+    {
+        if let Ok(vars) = process_declaration(src, node, uri) {
+            active_scope.vars.extend(vars);
         }
     } else if node.kind() == "struct_specifier" {
         if let Ok((name, lt)) = process_struct(src, node, uri) {
@@ -174,26 +291,47 @@ fn extract_recursively(
         if let Ok((name, lf)) = process_function(src, node, uri) {
             functions.insert(name, lf);
         }
-    } else if node.kind() == "preproc_def" {
+    } else if node.kind() == "preproc_def" || node.kind() == "preproc_function_def" {
         if let Ok((name, ld)) = process_define(src, node, uri) {
             defines.insert(name, ld);
         }
+    } else if node.kind() == "preproc_include" {
+        if let Ok(li) = process_include(src, node, uri) {
+            includes.push(li);
+        }
     }
 
     for child in node.children(&mut node.walk()) {
-        if child.kind() == "function_definition" {
+        if opens_scope(child) {
             let mut sub_scope = Scope {
                 vars: HashMap::new(),
                 scopes: vec![],
             };
-            extract_recursively(src, child, uri, types, functions, defines, &mut sub_scope);
-            active_scope.scopes.push((
-                child.start_position().row as u32,
-                child.end_position().row as u32,
-                sub_scope,
-            ));
+            extract_recursively(
+                src,
+                child,
+                uri,
+                types,
+                functions,
+                defines,
+                includes,
+                &mut sub_scope,
+            );
+            let range = node_to_utf16_range(src, child);
+            active_scope
+                .scopes
+                .push((range.start, range.end, sub_scope));
         } else {
-            extract_recursively(src, child, uri, types, functions, defines, active_scope);
+            extract_recursively(
+                src,
+                child,
+                uri,
+                types,
+                functions,
+                defines,
+                includes,
+                active_scope,
+            );
         }
     }
 }
@@ -202,32 +340,118 @@ fn extract_fn_calls_recursively(
     src: &str,
     node: Node,
     uri: &Url,
-    functions: &mut HashMap<String, LangFunc>,
+    calls: &mut Vec<(String, Location)>,
 ) {
     if node.kind() == "call_expression" {
         if let Some(function_node) = node.child_by_field_name("function") {
             let function_name = function_node.utf8_text(src.as_bytes()).unwrap().to_string();
-            if let Some(lf) = functions.get_mut(&function_name) {
-                lf.references.push(node_to_location(function_node, uri));
+            calls.push((function_name, node_to_location(src, function_node, uri)));
+        }
+    }
+    for child in node.children(&mut node.walk()) {
+        extract_fn_calls_recursively(src, child, uri, calls);
+    }
+}
+
+// true if the identifier is only written to, as in `x = 5` (compound assignments also read)
+fn is_write_only(src: &str, node: Node) -> bool {
+    let Some(parent) = node.parent() else {
+        return false;
+    };
+    if parent.kind() != "assignment_expression" {
+        return false;
+    }
+    let is_left = parent
+        .child_by_field_name("left")
+        .is_some_and(|left| left.id() == node.id());
+    let is_plain = parent
+        .child_by_field_name("operator")
+        .is_some_and(|op| op.utf8_text(src.as_bytes()).unwrap() == "=");
+    return is_left && is_plain;
+}
+
+// clears LangVar.unused for every var that is read somewhere other than its declaration
+fn mark_used_vars_recursively(src: &str, node: Node, uri: &Url, global_scope: &mut Scope) {
+    if node.kind() == "identifier" && !is_write_only(src, node) {
+        let name = node.utf8_text(src.as_bytes()).unwrap();
+        let location = node_to_location(src, node, uri);
+        if let Some(lv) = resolve_var_mut(global_scope, name, location.range.start) {
+            if lv.declaration_position.as_ref() != Some(&location) {
+                lv.unused = false;
             }
         }
     }
+
     for child in node.children(&mut node.walk()) {
-        extract_fn_calls_recursively(src, child, uri, functions);
+        mark_used_vars_recursively(src, child, uri, global_scope);
     }
 }
 
-pub fn parse(text: String, uri: &Url, lang_db: &LangDB) -> ParseState {
+// old_tree must already have every edit applied, tree sitter reuses the unchanged regions
+fn parse_tree(text: &str, old_tree: Option<&Tree>) -> Option<Tree> {
     let mut parser = Parser::new();
     parser
         .set_language(&tree_sitter_c::LANGUAGE.into())
         .expect("Failed to load grammar");
-    let tree = parser.parse(&text, None);
+    return parser.parse(text, old_tree);
+}
+
+pub fn parse(text: String, uri: &Url, lang_db: &LangDB) -> ParseState {
+    let tree = parse_tree(&text, None);
+    return build_parse_state(text, tree, uri, lang_db);
+}
+
+// applies TextDocumentSyncKind::INCREMENTAL changes (in order) to the previous state and reparses
+// a change without a range replaces the whole document
+pub fn parse_incremental(
+    old_state: ParseState,
+    changes: &[TextDocumentContentChangeEvent],
+    uri: &Url,
+    lang_db: &LangDB,
+) -> ParseState {
+    let mut text = old_state.text;
+    let mut tree = old_state.tree;
+
+    for change in changes {
+        match change.range {
+            Some(range) => {
+                let start_byte = position_to_byte(&text, range.start);
+                let old_end_byte = position_to_byte(&text, range.end).max(start_byte);
+                let start_position = byte_to_point(&text, start_byte);
+                let old_end_position = byte_to_point(&text, old_end_byte);
+
+                text.replace_range(start_byte..old_end_byte, &change.text);
+                let new_end_byte = start_byte + change.text.len();
+
+                if let Some(tree) = &mut tree {
+                    tree.edit(&InputEdit {
+                        start_byte,
+                        old_end_byte,
+                        new_end_byte,
+                        start_position,
+                        old_end_position,
+                        new_end_position: byte_to_point(&text, new_end_byte),
+                    });
+                }
+            }
+            None => {
+                text = change.text.clone();
+                tree = None;
+            }
+        }
+    }
+
+    let tree = parse_tree(&text, tree.as_ref());
+    return build_parse_state(text, tree, uri, lang_db);
+}
 
+fn build_parse_state(text: String, tree: Option<Tree>, uri: &Url, lang_db: &LangDB) -> ParseState {
     let mut types = lang_db.types.clone(); // TODO (perf) - this clone is not needed
     let mut functions = lang_db.functions.clone();
     let mut defines = lang_db.defines.clone();
     let mut keywords = vec![];
+    let mut calls = vec![];
+    let mut includes = vec![];
     let mut global_scope = Scope {
         vars: lang_db.builtin_vars.clone(),
         scopes: vec![],
@@ -253,11 +477,19 @@ pub fn parse(text: String, uri: &Url, lang_db: &LangDB) -> ParseState {
             &mut types,
             &mut functions,
             &mut defines,
+            &mut includes,
             &mut global_scope,
         );
 
+        mark_used_vars_recursively(&text, tree.root_node(), uri, &mut global_scope);
+
         // grab references at the end, once we know what all the functions are
-        extract_fn_calls_recursively(&text, tree.root_node(), uri, &mut functions)
+        extract_fn_calls_recursively(&text, tree.root_node(), uri, &mut calls);
+        for (function_name, location) in calls.iter() {
+            if let Some(lf) = functions.get_mut(function_name) {
+                lf.references.push(location.clone());
+            }
+        }
     }
 
     let ps = ParseState {
@@ -268,6 +500,9 @@ pub fn parse(text: String, uri: &Url, lang_db: &LangDB) -> ParseState {
         defines,
         keywords,
         global_scope,
+        calls,
+        includes,
+        type_rules: lang_db.type_rules.clone(),
     };
 
     //log::debug!("{:#?}", ps);
@@ -294,19 +529,14 @@ pub fn print_tree(src: &str, node: Node, depth: usize, field_name: Option<&str>)
         Some(field_name) => {
             println!(
                 "{}{:#?} {} {:#?}",
-                std::iter::repeat(" ").take(depth).collect::<String>(),
+                " ".repeat(depth),
                 field_name,
                 node.kind(),
                 fmt_contents
             );
         }
         None => {
-            println!(
-                "{}{} {:#?}",
-                std::iter::repeat(" ").take(depth).collect::<String>(),
-                node.kind(),
-                fmt_contents
-            );
+            println!("{}{} {:#?}", " ".repeat(depth), node.kind(), fmt_contents);
         }
     }
 
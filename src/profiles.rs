@@ -0,0 +1,91 @@
+use crate::lang_types::LangDB;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use tower_lsp::lsp_types::Url;
+
+// TODO lang specific - the profile used when no profiles are configured
+pub const DEFAULT_PROFILE_NAME: &str = "C-Style Lang Server";
+pub const DEFAULT_LANGUAGE_ID: &str = "cstyle";
+pub const DEFAULT_EXTENSIONS: [&str; 1] = ["cstyle"];
+
+// A language served by this server, each with its own LangDB
+// configured with the `profiles` initialization option
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileConfig {
+    // shown as the source of diagnostics
+    pub name: String,
+
+    // documents opened with one of these language ids use this profile
+    #[serde(default)]
+    pub language_ids: Vec<String>,
+
+    // otherwise documents (and workspace files) are matched by extension, without the leading '.'
+    #[serde(default)]
+    pub extensions: Vec<String>,
+
+    // relative paths are resolved against the first workspace folder
+    pub lang_db_path: Option<PathBuf>,
+}
+
+impl ProfileConfig {
+    pub fn default_profile(lang_db_path: Option<PathBuf>) -> ProfileConfig {
+        return ProfileConfig {
+            name: DEFAULT_PROFILE_NAME.to_owned(),
+            language_ids: vec![DEFAULT_LANGUAGE_ID.to_owned()],
+            extensions: DEFAULT_EXTENSIONS
+                .iter()
+                .map(|ext| ext.to_string())
+                .collect(),
+            lang_db_path,
+        };
+    }
+
+    pub fn matches_extension(&self, path: &Path) -> bool {
+        match path.extension() {
+            Some(ext) => self.extensions.iter().any(|e| *e == ext.to_string_lossy()),
+            None => false,
+        }
+    }
+}
+
+// canonical form of a path, so it can be watched and compared with the paths the client reports
+// paths that don't exist (yet) are only made absolute against the working directory
+pub fn absolute_path(path: &Path) -> PathBuf {
+    if let Ok(canonical) = path.canonicalize() {
+        return canonical;
+    }
+    return match std::env::current_dir() {
+        Ok(dir) => dir.join(path),
+        Err(_) => path.to_path_buf(),
+    };
+}
+
+#[derive(Debug)]
+pub struct LangProfile {
+    pub config: ProfileConfig,
+    pub lang_db: LangDB,
+}
+
+// the index of the profile for a document, matching language id first, then file extension
+// falls back to the first profile so every document is served
+pub fn profile_for(profiles: &[LangProfile], uri: &Url, language_id: Option<&str>) -> usize {
+    if let Some(language_id) = language_id {
+        if let Some(idx) = profiles
+            .iter()
+            .position(|p| p.config.language_ids.iter().any(|id| id == language_id))
+        {
+            return idx;
+        }
+    }
+
+    let path = Path::new(uri.path());
+    if let Some(idx) = profiles
+        .iter()
+        .position(|p| p.config.matches_extension(path))
+    {
+        return idx;
+    }
+
+    return 0;
+}
@@ -117,11 +117,11 @@ pub fn get_completions(
 ) -> Vec<CompletionItem> {
     let mut items: Vec<CompletionItem> = vec![];
 
-    let mut idents = lsp_util::extract_identifier_sequence(&sps.text, position);
+    let mut idents = lsp_util::extract_identifier_sequence(sps.text, position);
     match idents.pop() {
         Some(base_ident) => {
             if let Some(active_ident) = sps.vars.get(&base_ident) {
-                add_ident_completions(&mut items, active_ident, &mut idents, &sps);
+                add_ident_completions(&mut items, active_ident, &mut idents, sps);
             }
         }
         None => add_basic_completions(sps, &mut items),
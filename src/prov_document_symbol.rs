@@ -0,0 +1,177 @@
+use crate::lang_types::{scope_contains, LangVar, ParseState, Scope};
+use crate::lsp_util::{declared_in, node_to_utf16_range, position_to_byte};
+use crate::prov_signature_help::build_signature;
+use tower_lsp::lsp_types::*;
+
+pub fn capabilities() -> OneOf<bool, DocumentSymbolOptions> {
+    return OneOf::Left(true);
+}
+
+// the range of the node declaring the name at loc, such as the whole function for a function name
+fn full_range(ps: &ParseState, loc: &Location, kinds: &[&str]) -> Range {
+    let Some(tree) = &ps.tree else {
+        return loc.range;
+    };
+    let start = position_to_byte(&ps.text, loc.range.start);
+    let mut node = tree.root_node().descendant_for_byte_range(start, start);
+    while let Some(n) = node {
+        if kinds.contains(&n.kind()) {
+            return node_to_utf16_range(&ps.text, n);
+        }
+        node = n.parent();
+    }
+    return loc.range;
+}
+
+#[allow(deprecated)] // DocumentSymbol.deprecated has to be set
+fn symbol(
+    name: &str,
+    detail: Option<String>,
+    kind: SymbolKind,
+    range: Range,
+    selection_range: Range,
+    children: Vec<DocumentSymbol>,
+) -> DocumentSymbol {
+    return DocumentSymbol {
+        name: name.to_owned(),
+        detail,
+        kind,
+        tags: None,
+        deprecated: None,
+        range,
+        selection_range,
+        children: match children.is_empty() {
+            true => None,
+            false => Some(children),
+        },
+    };
+}
+
+fn var_symbol(
+    ps: &ParseState,
+    name: &str,
+    lv: &LangVar,
+    kind: SymbolKind,
+) -> Option<DocumentSymbol> {
+    let loc = lv.declaration_position.as_ref()?;
+    let range = full_range(
+        ps,
+        loc,
+        &["declaration", "parameter_declaration", "field_declaration"],
+    );
+    return Some(symbol(
+        name,
+        Some(lv.declaration_label("")),
+        kind,
+        range,
+        loc.range,
+        vec![],
+    ));
+}
+
+// locals of a function, including those of nested scopes
+fn add_scope_vars_recursively(
+    ps: &ParseState,
+    scope: &Scope,
+    uri: &Url,
+    items: &mut Vec<DocumentSymbol>,
+) {
+    for (name, lv) in scope.vars.iter() {
+        if declared_in(&lv.declaration_position, uri) {
+            items.extend(var_symbol(ps, name, lv, SymbolKind::VARIABLE));
+        }
+    }
+    for (_, _, sub_scope) in scope.scopes.iter() {
+        add_scope_vars_recursively(ps, sub_scope, uri, items);
+    }
+}
+
+fn sort_symbols(items: &mut [DocumentSymbol]) {
+    items.sort_by_key(|s| s.selection_range.start);
+}
+
+pub fn get_document_symbols(ps: &ParseState, uri: &Url) -> Vec<DocumentSymbol> {
+    let mut items = vec![];
+
+    for (name, lf) in ps.functions.iter() {
+        let Some(loc) = &lf.declaration_position else {
+            continue;
+        };
+        if loc.uri != *uri {
+            continue;
+        }
+
+        let range = full_range(ps, loc, &["function_definition"]);
+        let mut children = vec![];
+        for scope in ps.global_scope.scopes.iter() {
+            if scope_contains(scope, loc.range.start) {
+                add_scope_vars_recursively(ps, &scope.2, uri, &mut children);
+            }
+        }
+        sort_symbols(&mut children);
+
+        let detail = build_signature(name, lf).label;
+        items.push(symbol(
+            name,
+            Some(detail),
+            SymbolKind::FUNCTION,
+            range,
+            loc.range,
+            children,
+        ));
+    }
+
+    for (name, lt) in ps.types.iter() {
+        let Some(loc) = &lt.declaration_position else {
+            continue;
+        };
+        if loc.uri != *uri {
+            continue;
+        }
+
+        let range = full_range(ps, loc, &["struct_specifier"]);
+        let mut children = vec![];
+        for (field_name, lv) in lt.fields.iter() {
+            children.extend(var_symbol(ps, field_name, lv, SymbolKind::FIELD));
+        }
+        sort_symbols(&mut children);
+
+        items.push(symbol(
+            name,
+            None,
+            SymbolKind::STRUCT,
+            range,
+            loc.range,
+            children,
+        ));
+    }
+
+    for (name, ld) in ps.defines.iter() {
+        let Some(loc) = &ld.declaration_position else {
+            continue;
+        };
+        if loc.uri != *uri {
+            continue;
+        }
+
+        let range = full_range(ps, loc, &["preproc_def", "preproc_function_def"]);
+        let detail = Some(ld.insert_text.to_owned()).filter(|text| !text.is_empty());
+        items.push(symbol(
+            name,
+            detail,
+            SymbolKind::CONSTANT,
+            range,
+            loc.range,
+            vec![],
+        ));
+    }
+
+    for (name, lv) in ps.global_scope.vars.iter() {
+        if declared_in(&lv.declaration_position, uri) {
+            items.extend(var_symbol(ps, name, lv, SymbolKind::VARIABLE));
+        }
+    }
+
+    sort_symbols(&mut items);
+    return items;
+}
@@ -1,26 +1,218 @@
-use crate::lang_types;
+use crate::lang_types::ParseState;
+use crate::lsp_util::node_to_utf16_range;
 use tower_lsp::lsp_types::*;
+use tree_sitter::Node;
 
-#[allow(dead_code)]
 pub fn capabilities() -> FoldingRangeProviderCapability {
     FoldingRangeProviderCapability::Simple(true)
 }
 
-pub fn get_folding_ranges(scope: &lang_types::Scope) -> Vec<FoldingRange> {
-    let mut frs = vec![];
+// columns are in utf-16 units
+fn folding_range(start: Position, end: Position, kind: Option<FoldingRangeKind>) -> FoldingRange {
+    return FoldingRange {
+        start_line: start.line,
+        start_character: Some(start.character),
+        end_line: end.line,
+        end_character: Some(end.character),
+        kind,
+        collapsed_text: None,
+    };
+}
+
+// the position at the end of the line
+fn line_end(src: &str, row: usize) -> Position {
+    let len = src
+        .lines()
+        .nth(row)
+        .map_or(0, |line| line.encode_utf16().count());
+    return Position::new(row as u32, len as u32);
+}
+
+// true if only whitespace comes before the node on its line
+fn starts_line(src: &str, node: Node) -> bool {
+    let line_start = node.start_byte() - node.start_position().column;
+    return src[line_start..node.start_byte()].trim().is_empty();
+}
+
+// folds the inside of a `{ ... }` block, so the braces stay visible
+fn fold_braces(src: &str, node: Node, frs: &mut Vec<FoldingRange>) {
+    let mut cursor = node.walk();
+    let children: Vec<Node> = node.children(&mut cursor).collect();
+    let (Some(open), Some(close)) = (children.first(), children.last()) else {
+        return;
+    };
+    if open.kind() != "{" || close.kind() != "}" || close.is_missing() {
+        return;
+    }
+    let start = node_to_utf16_range(src, *open).end;
+    // clients that fold whole lines would hide a `}` on the last line, so end on the line before
+    let end = match starts_line(src, *close) {
+        true => line_end(src, close.start_position().row - 1),
+        false => node_to_utf16_range(src, *close).start,
+    };
+    if end.line > start.line {
+        frs.push(folding_range(start, end, None));
+    }
+}
 
-    for scope in scope.scopes.iter() {
-        frs.push(FoldingRange {
-            start_line: scope.0,
-            start_character: None,
-            end_line: scope.1,
-            end_character: None,
-            kind: Some(FoldingRangeKind::Region),
-            collapsed_text: None,
-        });
+// folds every branch of an #if / #ifdef up to the line before the next #elif, #else or #endif
+fn fold_directive_branches(src: &str, node: Node, frs: &mut Vec<FoldingRange>) {
+    let start_row = node.start_position().row;
+    let next = node.child_by_field_name("alternative").or_else(|| {
+        node.children(&mut node.walk())
+            .find(|c| c.kind() == "#endif")
+    });
+    let end_row = match next {
+        Some(next) => next.start_position().row,
+        None => node.end_position().row + 1,
+    };
+    if end_row > start_row + 1 {
+        frs.push(folding_range(
+            line_end(src, start_row),
+            line_end(src, end_row - 1),
+            Some(FoldingRangeKind::Region),
+        ));
+    }
+    if let Some(alternative) = node.child_by_field_name("alternative") {
+        fold_directive_branches(src, alternative, frs);
+    }
+}
 
-        frs.extend(get_folding_ranges(&scope.2));
+// TODO lang specific - markers for user defined regions
+fn region_marker(comment: &str) -> Option<bool> {
+    let text = comment.strip_prefix("//")?.trim();
+    if text.starts_with("endregion") {
+        return Some(false);
+    }
+    if text.starts_with("region") {
+        return Some(true);
     }
+    return None;
+}
+
+// folds multi-line block comments, runs of line comments and `// region` ... `// endregion` markers
+fn fold_comments(src: &str, comments: &[Node], frs: &mut Vec<FoldingRange>) {
+    let mut regions = vec![];
+    let mut run: Option<(Node, Node)> = None; // first and last comment of a run of line comments
+
+    for comment in comments.iter().copied() {
+        let text = comment.utf8_text(src.as_bytes()).unwrap();
+        let range = node_to_utf16_range(src, comment);
+        // trailing comments (`int x; // a`) are never part of a run
+        let is_line_comment = text.starts_with("//") && starts_line(src, comment);
+
+        let marker = region_marker(text);
+        match marker {
+            Some(true) => regions.push(comment),
+            Some(false) => {
+                if let Some(region) = regions.pop() {
+                    frs.push(folding_range(
+                        node_to_utf16_range(src, region).end,
+                        range.end,
+                        Some(FoldingRangeKind::Region),
+                    ));
+                }
+            }
+            None => (),
+        }
+
+        // a line comment continues the run if it is on the next line and isn't a marker
+        let continues = run
+            .is_some_and(|(_, last)| last.end_position().row + 1 == comment.start_position().row);
+        if is_line_comment && marker.is_none() && continues {
+            run = run.map(|(first, _)| (first, comment));
+            continue;
+        }
+        if let Some((first, last)) = run.take() {
+            fold_comment_run(src, first, last, frs);
+        }
+        if is_line_comment && marker.is_none() {
+            run = Some((comment, comment));
+        } else if text.starts_with("/*") && range.end.line > range.start.line {
+            frs.push(folding_range(
+                range.start,
+                range.end,
+                Some(FoldingRangeKind::Comment),
+            ));
+        }
+    }
+    if let Some((first, last)) = run {
+        fold_comment_run(src, first, last, frs);
+    }
+}
+
+fn fold_comment_run(src: &str, first: Node, last: Node, frs: &mut Vec<FoldingRange>) {
+    let start = node_to_utf16_range(src, first).start;
+    let end = node_to_utf16_range(src, last).end;
+    if end.line > start.line {
+        frs.push(folding_range(start, end, Some(FoldingRangeKind::Comment)));
+    }
+}
+
+fn fold_includes(src: &str, includes: &[Node], frs: &mut Vec<FoldingRange>) {
+    let mut idx = 0;
+    while idx < includes.len() {
+        // consecutive lines of #include fold together
+        let mut last = idx;
+        while last + 1 < includes.len()
+            && includes[last + 1].start_position().row == includes[last].start_position().row + 1
+        {
+            last += 1;
+        }
+        // preproc_include ends after the newline, so the end of its first line is used
+        let start = node_to_utf16_range(src, includes[idx]).start;
+        let end_row = includes[last].start_position().row;
+        if last > idx {
+            frs.push(folding_range(
+                start,
+                line_end(src, end_row),
+                Some(FoldingRangeKind::Imports),
+            ));
+        }
+        idx = last + 1;
+    }
+}
+
+fn extract_folding_ranges_recursively<'a>(
+    src: &str,
+    node: Node<'a>,
+    comments: &mut Vec<Node<'a>>,
+    includes: &mut Vec<Node<'a>>,
+    frs: &mut Vec<FoldingRange>,
+) {
+    match node.kind() {
+        "compound_statement"
+        | "field_declaration_list"
+        | "enumerator_list"
+        | "initializer_list" => fold_braces(src, node, frs),
+        "preproc_if" | "preproc_ifdef" => fold_directive_branches(src, node, frs),
+        "comment" => comments.push(node),
+        "preproc_include" => includes.push(node),
+        _ => (),
+    }
+    for child in node.children(&mut node.walk()) {
+        extract_folding_ranges_recursively(src, child, comments, includes, frs);
+    }
+}
+
+pub fn get_folding_ranges(ps: &ParseState) -> Vec<FoldingRange> {
+    let mut frs = vec![];
+    let Some(tree) = &ps.tree else {
+        return frs;
+    };
+
+    let mut comments = vec![];
+    let mut includes = vec![];
+    extract_folding_ranges_recursively(
+        &ps.text,
+        tree.root_node(),
+        &mut comments,
+        &mut includes,
+        &mut frs,
+    );
+    fold_comments(&ps.text, &comments, &mut frs);
+    fold_includes(&ps.text, &includes, &mut frs);
 
+    frs.sort_by_key(|fr| (fr.start_line, fr.start_character, fr.end_line));
     return frs;
 }
@@ -1,3 +1,4 @@
+use crate::symbols::{declaration_of, Symbol};
 use crate::{lang_types, lsp_util};
 use tower_lsp::lsp_types::*;
 
@@ -9,7 +10,7 @@ pub fn goto_definition(
     sps: &lang_types::ScopedParseState,
     position: Position,
 ) -> Option<GotoDefinitionResponse> {
-    let word = lsp_util::extract_word_at(&sps.text, position);
+    let word = lsp_util::extract_word_at(sps.text, position);
 
     if let Some(lf) = sps.functions.get(&word) {
         match &lf.declaration_position {
@@ -46,7 +47,7 @@ pub fn goto_type_definition(
     sps: &lang_types::ScopedParseState,
     position: Position,
 ) -> Option<GotoDefinitionResponse> {
-    let word = lsp_util::extract_word_at(&sps.text, position);
+    let word = lsp_util::extract_word_at(sps.text, position);
 
     if let Some(lv) = sps.vars.get(&word) {
         if let Some(lt) = sps.types.get(&lv.primary_type) {
@@ -81,14 +82,24 @@ pub fn references_capabilities() -> OneOf<bool, ReferencesOptions> {
     return OneOf::Left(true);
 }
 
+// filters the occurrences of a symbol down to its references, sorted by file and position
 pub fn goto_references(
-    sps: &lang_types::ScopedParseState,
-    position: Position,
-) -> Option<Vec<Location>> {
-    let word = lsp_util::extract_word_at(&sps.text, position);
-
-    if let Some(lf) = sps.functions.get(&word) {
-        return Some(lf.references.clone());
-    }
-    return None;
+    ps: &lang_types::ParseState,
+    symbol: &Symbol,
+    occurrences: Vec<Location>,
+    include_declaration: bool,
+) -> Vec<Location> {
+    let declaration = declaration_of(ps, symbol);
+    let mut references: Vec<Location> = occurrences
+        .into_iter()
+        .filter(|loc| include_declaration || declaration.as_ref() != Some(loc))
+        .collect();
+    references.sort_by_key(|loc| {
+        (
+            loc.uri.to_string(),
+            loc.range.start.line,
+            loc.range.start.character,
+        )
+    });
+    return references;
 }
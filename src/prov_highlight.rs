@@ -0,0 +1,141 @@
+use crate::lang_types::ParseState;
+use crate::lsp_util::{is_field, node_to_utf16_range, position_to_byte};
+use crate::symbols::{declaration_of, find_occurrences, name_node_at, symbol_at};
+use tower_lsp::lsp_types::*;
+use tree_sitter::Node;
+
+pub fn capabilities() -> OneOf<bool, DocumentHighlightOptions> {
+    return OneOf::Left(true);
+}
+
+// true if the name is declared or assigned here, such as `x` in `int x;`, `x = 1`, `x.y += 2` or `x++`
+fn is_write(node: Node) -> bool {
+    let mut target = node;
+    while let Some(parent) = target.parent() {
+        match parent.kind() {
+            "init_declarator"
+            | "array_declarator"
+            | "pointer_declarator"
+            | "function_declarator"
+            | "declaration"
+            | "parameter_declaration"
+            | "field_declaration" => return is_field(target, parent, "declarator"),
+            // `struct Name x;` only names the struct, the declaration has a body
+            "struct_specifier" => {
+                return is_field(target, parent, "name")
+                    && parent.child_by_field_name("body").is_some()
+            }
+            "preproc_def" | "preproc_function_def" => return is_field(target, parent, "name"),
+            "assignment_expression" => return is_field(target, parent, "left"),
+            "update_expression" => return true,
+            // writing to `a.b` or `a[i]` also writes to `a`
+            "field_expression" | "subscript_expression" => {
+                if !is_field(target, parent, "argument") && !is_field(target, parent, "field") {
+                    return false;
+                }
+            }
+            "parenthesized_expression" | "pointer_expression" => (),
+            _ => return false,
+        }
+        target = parent;
+    }
+    return false;
+}
+
+fn highlight(src: &str, node: Node, kind: DocumentHighlightKind) -> DocumentHighlight {
+    return DocumentHighlight {
+        range: node_to_utf16_range(src, node),
+        kind: Some(kind),
+    };
+}
+
+// the outermost #if / #ifdef of the group a directive belongs to
+fn directive_group_root(node: Node) -> Node {
+    let mut root = node;
+    while matches!(
+        root.kind(),
+        "preproc_elif" | "preproc_elifdef" | "preproc_else"
+    ) {
+        match root.parent() {
+            Some(parent) => root = parent,
+            None => break,
+        }
+    }
+    return root;
+}
+
+// the #if, #elif, #else and #endif keywords of a group, nested groups are skipped
+fn collect_directives(src: &str, node: Node, items: &mut Vec<DocumentHighlight>) {
+    for child in node.children(&mut node.walk()) {
+        if !child.is_named() && child.kind().starts_with('#') {
+            items.push(highlight(src, child, DocumentHighlightKind::TEXT));
+        }
+    }
+    if let Some(alternative) = node.child_by_field_name("alternative") {
+        collect_directives(src, alternative, items);
+    }
+}
+
+fn is_directive_group(node: Node) -> bool {
+    return matches!(
+        node.kind(),
+        "preproc_if" | "preproc_ifdef" | "preproc_elif" | "preproc_elifdef" | "preproc_else"
+    );
+}
+
+// matching preprocessor directives or braces for the token at the byte
+fn token_highlights(ps: &ParseState, byte: usize) -> Option<Vec<DocumentHighlight>> {
+    let root = ps.tree.as_ref()?.root_node();
+    let token = root.descendant_for_byte_range(byte, byte)?;
+    let parent = token.parent()?;
+
+    if !token.is_named() && token.kind().starts_with('#') && is_directive_group(parent) {
+        let mut items = vec![];
+        collect_directives(&ps.text, directive_group_root(parent), &mut items);
+        items.sort_by_key(|h| h.range.start); // #endif belongs to the outermost group
+        return Some(items);
+    }
+
+    if token.kind() == "{" || token.kind() == "}" {
+        let mut items = vec![];
+        for child in parent.children(&mut parent.walk()) {
+            if (child.kind() == "{" || child.kind() == "}") && !child.is_missing() {
+                items.push(highlight(&ps.text, child, DocumentHighlightKind::TEXT));
+            }
+        }
+        return Some(items);
+    }
+
+    return None;
+}
+
+pub fn get_highlights(
+    ps: &ParseState,
+    uri: &Url,
+    position: Position,
+) -> Option<Vec<DocumentHighlight>> {
+    // the token under the cursor, or the one just before it
+    let byte = position_to_byte(&ps.text, position);
+    if let Some(items) = token_highlights(ps, byte) {
+        return Some(items);
+    }
+    if let Some(items) = byte.checked_sub(1).and_then(|b| token_highlights(ps, b)) {
+        return Some(items);
+    }
+
+    let (symbol, _) = symbol_at(ps, position)?;
+    let declaration = declaration_of(ps, &symbol);
+
+    let mut items = vec![];
+    for loc in find_occurrences(ps, uri, &symbol) {
+        let Some(node) = name_node_at(ps, loc.range.start) else {
+            continue;
+        };
+        let kind = match declaration.as_ref() == Some(&loc) || is_write(node) {
+            true => DocumentHighlightKind::WRITE,
+            false => DocumentHighlightKind::READ,
+        };
+        items.push(highlight(&ps.text, node, kind));
+    }
+    return Some(items);
+}
@@ -6,7 +6,7 @@ pub fn capabilities() -> HoverProviderCapability {
 }
 
 pub fn get_hover(sps: &lang_types::ScopedParseState, position: Position) -> Option<Hover> {
-    let word = lsp_util::extract_word_at(&sps.text, position);
+    let word = lsp_util::extract_word_at(sps.text, position);
 
     if let Some(lt) = sps.types.get(&word) {
         let mut desc = "### ".to_owned() + &word + "\n---\n";
@@ -39,13 +39,16 @@ pub fn get_hover(sps: &lang_types::ScopedParseState, position: Position) -> Opti
     if let Some(lf) = sps.functions.get(&word) {
         let mut desc = "### ".to_owned() + &word + "\n---\n" + &lf.desc;
 
-        if lf.params.len() > 0 {
+        if lf.params.len() > 0 || lf.variadic {
             desc += "\n\nparams:\n";
             for (param_name, _) in lf.params.iter() {
                 desc += " - ";
                 desc += param_name;
                 desc += "\n\n";
             }
+            if lf.variadic {
+                desc += " - ...\n\n";
+            }
         }
 
         return Some(Hover {
@@ -61,11 +64,7 @@ pub fn get_hover(sps: &lang_types::ScopedParseState, position: Position) -> Opti
         return Some(Hover {
             contents: HoverContents::Markup(MarkupContent {
                 kind: MarkupKind::Markdown,
-                value: lv.primary_type.to_owned()
-                    + " "
-                    + &lv.type_qualifier_list.join(" ")
-                    + " "
-                    + &word,
+                value: lv.declaration_label(&word),
             }),
             range: None,
         });
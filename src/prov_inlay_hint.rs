@@ -1,5 +1,5 @@
 use crate::lang_types;
-use crate::lsp_util::point_to_position;
+use crate::lsp_util::node_start;
 use tower_lsp::lsp_types::*;
 use tree_sitter::Node;
 
@@ -44,7 +44,7 @@ fn process_call_expression(
                 ")" => {
                     for (param_name, _) in lf.params.iter().skip(param_counter) {
                         inlay_hints.push(InlayHint {
-                            position: point_to_position(node.start_position()),
+                            position: node_start(src, node),
                             label: InlayHintLabel::String(param_name.to_owned() + ":"),
                             kind: Some(InlayHintKind::PARAMETER),
                             text_edits: None,
@@ -56,15 +56,16 @@ fn process_call_expression(
                     }
                 }
                 _ => {
-                    let label = match lf.params.iter().nth(param_counter) {
+                    let label = match lf.params.get(param_counter) {
                         Some((param_name, _)) => param_name,
+                        None if lf.variadic => "", // extra arguments are expected
                         None => "?",
                     };
 
                     // don't render hint if the current argument is the hint label
-                    if label != node.utf8_text(src.as_bytes()).unwrap().to_string() {
+                    if !label.is_empty() && label != node.utf8_text(src.as_bytes()).unwrap() {
                         inlay_hints.push(InlayHint {
-                            position: point_to_position(node.start_position()),
+                            position: node_start(src, node),
                             label: InlayHintLabel::String(label.to_owned() + ":"),
                             kind: Some(InlayHintKind::PARAMETER),
                             text_edits: None,
@@ -0,0 +1,171 @@
+use crate::lang_types::{resolve_var, ParseState, Scope};
+use crate::lsp_util::{declared_in, node_to_utf16_range};
+use crate::symbols::{is_global_var, symbol_at, Symbol};
+use std::collections::HashMap;
+use tower_lsp::lsp_types::*;
+
+pub fn capabilities() -> OneOf<bool, RenameOptions> {
+    return OneOf::Right(RenameOptions {
+        prepare_provider: Some(true),
+        work_done_progress_options: WorkDoneProgressOptions {
+            work_done_progress: None,
+        },
+    });
+}
+
+// objects from the LangDB have no declaration in the workspace
+fn is_builtin(ps: &ParseState, symbol: &Symbol) -> bool {
+    match symbol {
+        Symbol::Var(_, declaration) => return declaration.is_none(),
+        Symbol::Function(name) => {
+            return ps
+                .functions
+                .get(name)
+                .is_none_or(|lf| lf.declaration_position.is_none())
+        }
+        Symbol::Type(name) | Symbol::Field(name, _) => {
+            return ps
+                .types
+                .get(name)
+                .is_none_or(|lt| lt.builtin || lt.declaration_position.is_none())
+        }
+        Symbol::Define(name) => {
+            return ps
+                .defines
+                .get(name)
+                .is_none_or(|ld| ld.declaration_position.is_none())
+        }
+    }
+}
+
+// the symbol at the position, if it can be renamed, along with the range of the name
+pub fn prepare_rename(
+    ps: &ParseState,
+    uri: &Url,
+    position: Position,
+) -> Result<(Symbol, Range), String> {
+    let Some((symbol, node)) = symbol_at(ps, position) else {
+        return Err("there is no symbol to rename here".to_owned());
+    };
+
+    if is_builtin(ps, &symbol) {
+        return Err(format!("can't rename builtin '{}'", symbol.name()));
+    }
+    // locals are only renamed within one document, globals are renamed across the workspace
+    if let Symbol::Var(_, declaration) = &symbol {
+        if !declared_in(declaration, uri) && !is_global_var(ps, &symbol) {
+            return Err(format!(
+                "can't rename '{}', it is declared in another file",
+                symbol.name()
+            ));
+        }
+    }
+
+    return Ok((symbol, node_to_utf16_range(&ps.text, node)));
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    return chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+}
+
+// TODO lang specific - reserved words, builtin types are treated as keywords
+fn is_keyword(ps: &ParseState, name: &str) -> bool {
+    return ps.keywords.iter().any(|(_, label)| label == name)
+        || ps.types.get(name).is_some_and(|lt| lt.builtin);
+}
+
+// true if the scope, or any scope nested in it, declares name (wherever in the scope it is declared)
+fn declares_recursively(scope: &Scope, name: &str) -> bool {
+    return scope.vars.contains_key(name)
+        || scope
+            .scopes
+            .iter()
+            .any(|(_, _, sub_scope)| declares_recursively(sub_scope, name));
+}
+
+// the scope a var is declared in
+fn declaring_scope<'a>(scope: &'a Scope, name: &str, declaration: &Location) -> Option<&'a Scope> {
+    if scope
+        .vars
+        .get(name)
+        .is_some_and(|lv| lv.declaration_position.as_ref() == Some(declaration))
+    {
+        return Some(scope);
+    }
+    for (_, _, sub_scope) in scope.scopes.iter() {
+        if let Some(found) = declaring_scope(sub_scope, name, declaration) {
+            return Some(found);
+        }
+    }
+    return None;
+}
+
+// refuses new names that aren't identifiers, are keywords, or would collide with an existing symbol
+// occurrences are the places in this document the symbol is named
+pub fn check_new_name(
+    ps: &ParseState,
+    symbol: &Symbol,
+    new_name: &str,
+    occurrences: &[Location],
+) -> Result<(), String> {
+    if !is_identifier(new_name) {
+        return Err(format!("'{}' is not a valid identifier", new_name));
+    }
+    if is_keyword(ps, new_name) {
+        return Err(format!("'{}' is a keyword", new_name));
+    }
+
+    // a var with the new name would shadow (or be shadowed by) the renamed symbol
+    let var_visible = occurrences
+        .iter()
+        .any(|loc| resolve_var(&ps.global_scope, new_name, loc.range.start).is_some());
+
+    let collides = match symbol {
+        Symbol::Var(name, declaration) => {
+            // declaration order doesn't matter, a later `int b;` would be a redeclaration
+            let declared_nearby = declaration
+                .as_ref()
+                .and_then(|declaration| declaring_scope(&ps.global_scope, name, declaration))
+                .is_some_and(|scope| declares_recursively(scope, new_name));
+            var_visible
+                || declared_nearby
+                || ps.functions.contains_key(new_name)
+                || ps.defines.contains_key(new_name)
+        }
+        Symbol::Function(_) | Symbol::Define(_) => {
+            var_visible
+                || ps.functions.contains_key(new_name)
+                || ps.defines.contains_key(new_name)
+                || ps.types.contains_key(new_name)
+                || ps.global_scope.vars.contains_key(new_name)
+        }
+        Symbol::Type(_) => ps.types.contains_key(new_name) || ps.defines.contains_key(new_name),
+        Symbol::Field(owner, _) => ps
+            .types
+            .get(owner)
+            .is_some_and(|lt| lt.fields.contains_key(new_name)),
+    };
+    if collides {
+        return Err(format!("'{}' is already declared", new_name));
+    }
+
+    return Ok(());
+}
+
+pub fn rename_edits(locations: Vec<Location>, new_name: &str) -> WorkspaceEdit {
+    let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+    for loc in locations {
+        changes.entry(loc.uri).or_default().push(TextEdit {
+            range: loc.range,
+            new_text: new_name.to_owned(),
+        });
+    }
+    return WorkspaceEdit {
+        changes: Some(changes),
+        ..Default::default()
+    };
+}
@@ -1,114 +1,332 @@
-use crate::lang_types;
+use crate::lang_types::{self, resolve_var, LangVar};
+use crate::lsp_util::{
+    declared_in, is_declaration_site, is_unused_exempt, node_start, node_to_utf16_range,
+};
+use crate::symbols::{resolve_node, Symbol};
 use tower_lsp::lsp_types::*;
 use tree_sitter::Node;
 
+#[allow(non_camel_case_types)]
 pub enum LangSemanticToken {
     FUNCTION,
     NUMBER,
     MACRO,
     PARAMETER,
     STRUCT,
+    VARIABLE,
+    PROPERTY,
+    ENUM_MEMBER,
 }
-const TOKEN_TYPES: [SemanticTokenType; 5] = [
+const TOKEN_TYPES: [SemanticTokenType; 8] = [
     SemanticTokenType::FUNCTION,
     SemanticTokenType::NUMBER,
     SemanticTokenType::MACRO,
     SemanticTokenType::PARAMETER,
     SemanticTokenType::STRUCT,
+    SemanticTokenType::VARIABLE,
+    SemanticTokenType::PROPERTY,
+    SemanticTokenType::ENUM_MEMBER,
 ];
 
+#[allow(non_camel_case_types)]
+pub enum LangSemanticModifier {
+    DECLARATION,
+    READONLY,
+    DEFAULT_LIBRARY,
+    DEPRECATED,
+    UNUSED,
+}
+fn token_modifiers() -> Vec<SemanticTokenModifier> {
+    return vec![
+        SemanticTokenModifier::DECLARATION,
+        SemanticTokenModifier::READONLY,
+        SemanticTokenModifier::DEFAULT_LIBRARY,
+        SemanticTokenModifier::DEPRECATED,
+        SemanticTokenModifier::new("unused"), // not a standard modifier, lets themes dim unused locals
+    ];
+}
+
+impl LangSemanticModifier {
+    pub fn bit(self) -> u32 {
+        return 1 << self as u32;
+    }
+}
+
 struct SimpleToken {
     row: usize,
     col: usize,
     len: usize,
     token_type: LangSemanticToken,
+    modifiers: u32,
 }
 
 pub fn capabilities() -> SemanticTokensServerCapabilities {
     SemanticTokensServerCapabilities::SemanticTokensOptions(SemanticTokensOptions {
         legend: SemanticTokensLegend {
             token_types: TOKEN_TYPES.to_vec(),
-            token_modifiers: vec![],
+            token_modifiers: token_modifiers(),
         },
 
-        full: Some(SemanticTokensFullOptions::Bool(true)),
+        full: Some(SemanticTokensFullOptions::Delta { delta: Some(true) }),
+        range: Some(true),
         ..Default::default()
     })
 }
 
-fn extract_sm_tokens_recursively(
+fn push_token(
     src: &str,
-    node: Node,
-    parse_state: &lang_types::ParseState,
     simple_tokens: &mut Vec<SimpleToken>,
+    node: Node,
+    token_type: LangSemanticToken,
+    modifiers: u32,
 ) {
+    let start = node_to_utf16_range(src, node).start;
+    simple_tokens.push(SimpleToken {
+        row: start.line as usize,
+        col: start.character as usize,
+        len: src[node.start_byte()..node.end_byte()]
+            .encode_utf16()
+            .count(),
+        token_type,
+        modifiers,
+    });
+}
+
+// the function a var is a parameter of
+fn param_of<'a>(
+    parse_state: &'a lang_types::ParseState,
+    declaration: &Option<Location>,
+) -> Option<&'a str> {
+    declaration.as_ref()?;
+    for (name, lf) in parse_state.functions.iter() {
+        if lf
+            .params
+            .iter()
+            .any(|(_, lv)| lv.declaration_position == *declaration)
+        {
+            return Some(name);
+        }
+    }
+    return None;
+}
+
+// true if the var itself is `const`, `const char *s` points to const chars but can be reassigned
+fn is_const(lv: &LangVar) -> bool {
+    return lv
+        .type_qualifier_list
+        .iter()
+        .rev()
+        .take_while(|q| *q != "*")
+        .any(|q| q == "const");
+}
+
+fn var_modifiers(
+    parse_state: &lang_types::ParseState,
+    uri: &Url,
+    name: &str,
+    lv: &LangVar,
+    param_of: Option<&str>,
+) -> u32 {
+    let mut modifiers = 0;
+    if lv.declaration_position.is_none() {
+        modifiers |= LangSemanticModifier::DEFAULT_LIBRARY.bit();
+    }
+    if is_const(lv) {
+        modifiers |= LangSemanticModifier::READONLY.bit();
+    }
+
+    // same rules as the unused diagnostics, globals may be used by other files
+    let is_global = parse_state
+        .global_scope
+        .vars
+        .get(name)
+        .is_some_and(|global| global.declaration_position == lv.declaration_position);
+    if lv.unused
+        && !is_global
+        && !is_unused_exempt(name)
+        && param_of != Some("main")
+        && declared_in(&lv.declaration_position, uri)
+    {
+        modifiers |= LangSemanticModifier::UNUSED.bit();
+    }
+    return modifiers;
+}
+
+// TODO lang specific - enums aren't tracked by the parser, so enumerators are found by syntax
+fn collect_enumerators_recursively(src: &str, node: Node, names: &mut Vec<String>) {
+    if node.kind() == "enumerator" {
+        if let Some(name) = node.child_by_field_name("name") {
+            names.push(name.utf8_text(src.as_bytes()).unwrap().to_owned());
+        }
+    }
     for child in node.children(&mut node.walk()) {
-        if child.kind() == "identifier" {
-            let name = child.utf8_text(src.as_bytes()).unwrap();
-            if parse_state.functions.contains_key(name) {
-                simple_tokens.push(SimpleToken {
-                    row: child.start_position().row,
-                    col: child.start_position().column,
-                    len: name.len(),
-                    token_type: LangSemanticToken::FUNCTION,
-                });
+        collect_enumerators_recursively(src, child, names);
+    }
+}
+
+fn identifier_token(
+    src: &str,
+    node: Node,
+    parse_state: &lang_types::ParseState,
+    uri: &Url,
+    enumerators: &[String],
+) -> Option<(LangSemanticToken, u32)> {
+    // parameters of prototypes aren't params of a LangFunc
+    let in_params = node.parent()?.kind() == "parameter_declaration";
+
+    let name = node.utf8_text(src.as_bytes()).unwrap();
+    match resolve_node(parse_state, node) {
+        Some(Symbol::Var(..)) => {
+            let position = node_start(src, node);
+            let lv = resolve_var(&parse_state.global_scope, name, position)?;
+            let param_of = param_of(parse_state, &lv.declaration_position);
+            let modifiers = var_modifiers(parse_state, uri, name, lv, param_of);
+            match param_of {
+                Some(_) => return Some((LangSemanticToken::PARAMETER, modifiers)),
+                None if in_params => return Some((LangSemanticToken::PARAMETER, 0)),
+                None => return Some((LangSemanticToken::VARIABLE, modifiers)),
+            }
+        }
+        Some(Symbol::Function(_)) => {
+            let lf = parse_state.functions.get(name)?;
+            let mut modifiers = 0;
+            if lf.declaration_position.is_none() {
+                modifiers |= LangSemanticModifier::DEFAULT_LIBRARY.bit();
             }
-            if parse_state.defines.contains_key(name) {
-                simple_tokens.push(SimpleToken {
-                    row: child.start_position().row,
-                    col: child.start_position().column,
-                    len: name.len(),
-                    token_type: LangSemanticToken::MACRO,
-                });
+            if lf.deprecated {
+                modifiers |= LangSemanticModifier::DEPRECATED.bit();
             }
-            // TODO - highlight vars within scope
+            return Some((LangSemanticToken::FUNCTION, modifiers));
         }
-        if child.kind() == "number_literal" {
-            let name = child.utf8_text(src.as_bytes()).unwrap();
-            simple_tokens.push(SimpleToken {
-                row: child.start_position().row,
-                col: child.start_position().column,
-                len: name.len(),
-                token_type: LangSemanticToken::NUMBER,
-            });
+        Some(Symbol::Define(_)) => {
+            let ld = parse_state.defines.get(name)?;
+            let modifiers = match ld.declaration_position {
+                Some(_) => 0,
+                None => LangSemanticModifier::DEFAULT_LIBRARY.bit(),
+            };
+            return Some((LangSemanticToken::MACRO, modifiers));
         }
-        if node.kind() == "parameter_declaration" && child.kind() == "identifier" {
-            let name = child.utf8_text(src.as_bytes()).unwrap();
-            simple_tokens.push(SimpleToken {
-                row: child.start_position().row,
-                col: child.start_position().column,
-                len: name.len(),
-                token_type: LangSemanticToken::PARAMETER, // TODO - also highlight within scope, change color if unused
-            });
+        _ => (),
+    }
+
+    if in_params {
+        return Some((LangSemanticToken::PARAMETER, 0));
+    }
+    if enumerators.iter().any(|n| n == name) {
+        return Some((LangSemanticToken::ENUM_MEMBER, 0));
+    }
+    return None;
+}
+
+fn field_token(
+    node: Node,
+    parse_state: &lang_types::ParseState,
+) -> Option<(LangSemanticToken, u32)> {
+    let Some(Symbol::Field(owner, name)) = resolve_node(parse_state, node) else {
+        return None;
+    };
+    let lt = parse_state.types.get(&owner)?;
+    let mut modifiers = 0;
+    if lt.builtin {
+        modifiers |= LangSemanticModifier::DEFAULT_LIBRARY.bit();
+    }
+    if is_const(lt.fields.get(&name)?) {
+        modifiers |= LangSemanticModifier::READONLY.bit();
+    }
+    return Some((LangSemanticToken::PROPERTY, modifiers));
+}
+
+// `struct Name x;` names a struct without declaring it
+fn is_declaration(node: Node) -> bool {
+    if node.kind() == "type_identifier" {
+        return node.parent().is_some_and(|p| {
+            p.kind() == "struct_specifier"
+                && p.child_by_field_name("name") == Some(node)
+                && p.child_by_field_name("body").is_some()
+        });
+    }
+    return is_declaration_site(node);
+}
+
+fn extract_sm_tokens_recursively(
+    src: &str,
+    node: Node,
+    parse_state: &lang_types::ParseState,
+    uri: &Url,
+    enumerators: &[String],
+    range: Option<Range>,
+    simple_tokens: &mut Vec<SimpleToken>,
+) {
+    for child in node.children(&mut node.walk()) {
+        if let Some(range) = range {
+            // skip subtrees outside of the requested range
+            let child_range = node_to_utf16_range(src, child);
+            if child_range.end < range.start || child_range.start >= range.end {
+                continue;
+            }
         }
-        if child.kind() == "type_identifier" {
-            let name = child.utf8_text(src.as_bytes()).unwrap();
-            if let Some(lt) = parse_state.types.get(name) {
-                if !lt.builtin {
-                    simple_tokens.push(SimpleToken {
-                        row: child.start_position().row,
-                        col: child.start_position().column,
-                        len: name.len(),
-                        token_type: LangSemanticToken::STRUCT,
-                    });
+
+        let token = match child.kind() {
+            "identifier" => identifier_token(src, child, parse_state, uri, enumerators),
+            "field_identifier" => field_token(child, parse_state),
+            "number_literal" => Some((LangSemanticToken::NUMBER, 0)),
+            "type_identifier" => {
+                let name = child.utf8_text(src.as_bytes()).unwrap();
+                match parse_state.types.get(name) {
+                    Some(lt) if !lt.builtin => Some((LangSemanticToken::STRUCT, 0)),
+                    _ => None,
                 }
             }
+            _ => None,
+        };
+        if let Some((token_type, mut modifiers)) = token {
+            if is_declaration(child) {
+                modifiers |= LangSemanticModifier::DECLARATION.bit();
+            }
+            push_token(src, simple_tokens, child, token_type, modifiers);
         }
 
-        extract_sm_tokens_recursively(src, child, parse_state, simple_tokens);
+        extract_sm_tokens_recursively(
+            src,
+            child,
+            parse_state,
+            uri,
+            enumerators,
+            range,
+            simple_tokens,
+        );
     }
 }
 
-pub fn get_sm_tokens(parse_state: &lang_types::ParseState) -> Vec<SemanticToken> {
+// tokens of the whole document, or only those that start within range
+pub fn get_sm_tokens(
+    parse_state: &lang_types::ParseState,
+    uri: &Url,
+    range: Option<Range>,
+) -> Vec<SemanticToken> {
     let mut simple_tokens = vec![];
     if let Some(tree) = &parse_state.tree {
+        let mut enumerators = vec![];
+        collect_enumerators_recursively(&parse_state.text, tree.root_node(), &mut enumerators);
         extract_sm_tokens_recursively(
             &parse_state.text,
             tree.root_node(),
             parse_state,
+            uri,
+            &enumerators,
+            range,
             &mut simple_tokens,
         );
     }
+    if let Some(range) = range {
+        simple_tokens.retain(|st| {
+            let start = Position {
+                line: st.row as u32,
+                character: st.col as u32,
+            };
+            return range.start <= start && start < range.end;
+        });
+    }
 
     let mut start_row = 0;
     let mut start_col = 0;
@@ -126,7 +344,7 @@ pub fn get_sm_tokens(parse_state: &lang_types::ParseState) -> Vec<SemanticToken>
             delta_start,
             length: st.len as u32,
             token_type: st.token_type as u32,
-            token_modifiers_bitset: 0,
+            token_modifiers_bitset: st.modifiers,
         });
         start_row = st.row as u32;
         start_col = st.col as u32
@@ -134,3 +352,31 @@ pub fn get_sm_tokens(parse_state: &lang_types::ParseState) -> Vec<SemanticToken>
 
     return sm_tokens;
 }
+
+// edits that turn the old tokens into the new ones, a single edit replacing everything between the
+// unchanged prefix and suffix
+// starts and counts are in integers of the encoded array, every token is 5 integers
+pub fn get_sm_token_edits(old: &[SemanticToken], new: &[SemanticToken]) -> Vec<SemanticTokensEdit> {
+    let prefix = old
+        .iter()
+        .zip(new.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let deleted = old.len() - prefix - suffix;
+    let inserted = &new[prefix..new.len() - suffix];
+    if deleted == 0 && inserted.is_empty() {
+        return vec![];
+    }
+    return vec![SemanticTokensEdit {
+        start: (prefix * 5) as u32,
+        delete_count: (deleted * 5) as u32,
+        data: Some(inserted.to_vec()),
+    }];
+}
@@ -1,5 +1,6 @@
 use crate::{lang_types, lsp_util};
 use tower_lsp::lsp_types::*;
+use tree_sitter::Node;
 
 pub fn capabilities() -> SignatureHelpOptions {
     SignatureHelpOptions {
@@ -10,3 +11,131 @@ pub fn capabilities() -> SignatureHelpOptions {
         },
     }
 }
+
+// finds the innermost call whose argument list contains the byte offset
+// returns the function name and the number of commas before the offset
+fn find_call_in_tree(src: &str, root: Node, byte: usize) -> Option<(String, u32)> {
+    let mut node = root.descendant_for_byte_range(byte, byte)?;
+    loop {
+        if node.kind() == "call_expression" {
+            let function_node = node.child_by_field_name("function")?;
+            let arguments_node = node.child_by_field_name("arguments")?;
+
+            // the cursor has to be after the '(' and before a closing ')'
+            let closed = arguments_node
+                .child(arguments_node.child_count().saturating_sub(1))
+                .is_some_and(|last| last.kind() == ")" && !last.is_missing());
+            let inside =
+                arguments_node.start_byte() < byte && (byte < arguments_node.end_byte() || !closed);
+            if inside {
+                let mut commas = 0;
+                for child in arguments_node.children(&mut arguments_node.walk()) {
+                    if child.kind() == "," && child.start_byte() < byte {
+                        commas += 1;
+                    }
+                }
+                let name = function_node.utf8_text(src.as_bytes()).unwrap().to_string();
+                return Some((name, commas));
+            }
+        }
+        node = node.parent()?;
+    }
+}
+
+// fallback for incomplete code (such as `foo(a, `), where tree sitter doesn't produce a call_expression
+// walks back from the offset to the unmatched '(' and the identifier before it
+fn find_call_in_text(src: &str, byte: usize) -> Option<(String, u32)> {
+    let bytes = src.as_bytes();
+    let mut depth = 0;
+    let mut commas = 0;
+    let mut pos = byte.min(bytes.len());
+
+    while pos > 0 {
+        pos -= 1;
+        match bytes[pos] {
+            b')' | b']' | b'}' => depth += 1,
+            b'[' | b'{' if depth > 0 => depth -= 1,
+            b'{' | b';' => return None, // left the statement
+            b'(' if depth > 0 => depth -= 1,
+            b'(' => {
+                let end = src[..pos].trim_end().len();
+                let start = src[..end]
+                    .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .map_or(0, |i| i + 1);
+                if start == end {
+                    return None;
+                }
+                return Some((src[start..end].to_string(), commas));
+            }
+            b',' if depth == 0 => commas += 1,
+            _ => (),
+        }
+    }
+    return None;
+}
+
+fn utf16_len(s: &str) -> u32 {
+    return s.encode_utf16().count() as u32;
+}
+
+pub fn build_signature(name: &str, lf: &lang_types::LangFunc) -> SignatureInformation {
+    let mut label = lf.return_type.to_owned() + " " + name + "(";
+    let mut parameters = vec![];
+
+    for (idx, (param_name, lv)) in lf.params.iter().enumerate() {
+        if idx > 0 {
+            label += ", ";
+        }
+        let start = utf16_len(&label);
+        label += &lv.declaration_label(param_name);
+        parameters.push(ParameterInformation {
+            label: ParameterLabel::LabelOffsets([start, utf16_len(&label)]),
+            documentation: None,
+        });
+    }
+    if lf.variadic {
+        label += match lf.params.is_empty() {
+            true => "...",
+            false => ", ...",
+        };
+    }
+    label += ")";
+
+    let documentation = match lf.desc.is_empty() {
+        true => None,
+        false => Some(Documentation::MarkupContent(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: lf.desc.to_owned(),
+        })),
+    };
+
+    return SignatureInformation {
+        label,
+        documentation,
+        parameters: Some(parameters),
+        active_parameter: None,
+    };
+}
+
+pub fn get_signature_help(
+    sps: &lang_types::ScopedParseState,
+    position: Position,
+) -> Option<SignatureHelp> {
+    let byte = lsp_util::position_to_byte(sps.text, position);
+
+    let mut call = None;
+    if let Some(tree) = sps.tree {
+        call = find_call_in_tree(sps.text, tree.root_node(), byte);
+    }
+    let (name, commas) = match call {
+        Some(call) => call,
+        None => find_call_in_text(sps.text, byte)?,
+    };
+
+    let lf = sps.functions.get(&name)?;
+    return Some(SignatureHelp {
+        signatures: vec![build_signature(&name, lf)],
+        active_signature: Some(0),
+        active_parameter: Some(commas),
+    });
+}
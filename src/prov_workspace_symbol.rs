@@ -0,0 +1,150 @@
+use crate::workspace_index::{IndexedFile, WorkspaceIndex};
+use tower_lsp::lsp_types::*;
+
+// results past this are dropped, the client asks again as the query gets longer
+const MAX_RESULTS: usize = 128;
+
+pub fn capabilities() -> OneOf<bool, WorkspaceSymbolOptions> {
+    return OneOf::Right(WorkspaceSymbolOptions {
+        resolve_provider: Some(true),
+        work_done_progress_options: WorkDoneProgressOptions {
+            work_done_progress: None,
+        },
+    });
+}
+
+fn is_word_start(name: &[char], idx: usize) -> bool {
+    return idx == 0
+        || name[idx - 1] == '_'
+        || (name[idx].is_uppercase() && name[idx - 1].is_lowercase());
+}
+
+// scores how well the query matches the name as a case insensitive subsequence, None if it doesn't
+// exact and prefix matches rank first, then consecutive matches and matches at the start of words
+pub fn fuzzy_score(query: &str, name: &str) -> Option<i32> {
+    let query: Vec<char> = query.chars().map(|c| c.to_ascii_lowercase()).collect();
+    let chars: Vec<char> = name.chars().collect();
+    let lower: Vec<char> = chars.iter().map(|c| c.to_ascii_lowercase()).collect();
+    if query.len() > chars.len() {
+        return None;
+    }
+
+    let mut score = 0;
+    if !query.is_empty() {
+        // best[j] is the best score with the current query char matched at name[j]
+        let mut best: Vec<Option<i32>> = vec![None; chars.len()];
+        for (i, q) in query.iter().enumerate() {
+            let mut next = vec![None; chars.len()];
+            let mut best_before: Option<i32> = None; // best of the previous row before j - 1
+            for j in 0..chars.len() {
+                if j >= 2 {
+                    best_before = best_before.max(best[j - 2]);
+                }
+                if lower[j] != *q {
+                    continue;
+                }
+
+                let mut bonus = 1;
+                if is_word_start(&chars, j) {
+                    bonus += 10;
+                }
+                next[j] = match i {
+                    0 => Some(bonus),
+                    _ => {
+                        let consecutive = j.checked_sub(1).and_then(|k| best[k]).map(|s| s + 5);
+                        consecutive.max(best_before).map(|s| s + bonus)
+                    }
+                };
+            }
+            best = next;
+        }
+        score = best.into_iter().flatten().max()?;
+    }
+
+    if lower == query {
+        score += 100;
+    } else if lower.starts_with(&query) {
+        score += 50;
+    }
+    // shorter names are closer matches
+    score -= (chars.len() - query.len()) as i32 / 4;
+    return Some(score);
+}
+
+// every symbol a file declares (name, kind, location)
+fn file_symbols(indexed: &IndexedFile) -> Vec<(&String, SymbolKind, &Option<Location>)> {
+    let mut symbols = vec![];
+    for (name, lf) in indexed.functions.iter() {
+        symbols.push((name, SymbolKind::FUNCTION, &lf.declaration_position));
+    }
+    for (name, lt) in indexed.types.iter() {
+        symbols.push((name, SymbolKind::STRUCT, &lt.declaration_position));
+    }
+    for (name, lv) in indexed.globals.iter() {
+        symbols.push((name, SymbolKind::VARIABLE, &lv.declaration_position));
+    }
+    for (name, ld) in indexed.defines.iter() {
+        symbols.push((name, SymbolKind::CONSTANT, &ld.declaration_position));
+    }
+    return symbols;
+}
+
+#[allow(deprecated)] // SymbolInformation.deprecated has to be set
+pub fn get_workspace_symbols(index: &WorkspaceIndex, query: &str) -> Vec<SymbolInformation> {
+    let mut matches = vec![];
+    for indexed in index.files.values() {
+        for (name, kind, location) in file_symbols(indexed) {
+            let Some(location) = location else {
+                continue;
+            };
+            if let Some(score) = fuzzy_score(query, name) {
+                matches.push((score, name, kind, location));
+            }
+        }
+    }
+
+    // best first, ties are sorted by name and file so results are stable
+    matches.sort_by(|a, b| {
+        b.0.cmp(&a.0)
+            .then(a.1.cmp(b.1))
+            .then(a.3.uri.as_str().cmp(b.3.uri.as_str()))
+    });
+    matches.truncate(MAX_RESULTS);
+
+    return matches
+        .into_iter()
+        .map(|(_, name, kind, location)| SymbolInformation {
+            name: name.to_owned(),
+            kind,
+            tags: None,
+            deprecated: None,
+            location: location.clone(),
+            container_name: None,
+        })
+        .collect();
+}
+
+// fills in the range of a symbol that only has a file
+pub fn resolve_workspace_symbol(
+    index: &WorkspaceIndex,
+    symbol: WorkspaceSymbol,
+) -> WorkspaceSymbol {
+    let OneOf::Right(WorkspaceLocation { uri }) = &symbol.location else {
+        return symbol;
+    };
+    let Some(indexed) = index.files.get(uri) else {
+        return symbol;
+    };
+
+    for (name, kind, location) in file_symbols(indexed) {
+        if *name == symbol.name && kind == symbol.kind {
+            if let Some(location) = location {
+                return WorkspaceSymbol {
+                    location: OneOf::Left(location.clone()),
+                    ..symbol
+                };
+            }
+        }
+    }
+    return symbol;
+}
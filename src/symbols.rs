@@ -0,0 +1,348 @@
+use super::Backend;
+use crate::includes::merge_includes;
+use crate::lang_types::{resolve_var, LangDB, ParseState};
+use crate::lsp_util::{node_start, node_to_location, position_to_byte};
+use crate::parser;
+use crate::profiles::LangProfile;
+use crate::type_inference::field_owner;
+use crate::workspace_index::WorkspaceIndex;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use tower_lsp::lsp_types::*;
+use tree_sitter::Node;
+
+// A named object that a name in the document refers to
+// vars are identified by their declaration, so shadowed vars with the same name are distinct
+#[derive(Debug, Clone, PartialEq)]
+pub enum Symbol {
+    // name, declaration (None for builtins)
+    Var(String, Option<Location>),
+    Function(String),
+    Type(String),
+    // struct name, field name
+    Field(String, String),
+    Define(String),
+}
+
+impl Symbol {
+    pub fn name(&self) -> &str {
+        match self {
+            Symbol::Var(name, _) => return name,
+            Symbol::Function(name) => return name,
+            Symbol::Type(name) => return name,
+            Symbol::Field(_, name) => return name,
+            Symbol::Define(name) => return name,
+        }
+    }
+}
+
+// where a symbol is declared, None for builtins
+pub fn declaration_of(ps: &ParseState, symbol: &Symbol) -> Option<Location> {
+    match symbol {
+        Symbol::Var(_, declaration) => return declaration.clone(),
+        Symbol::Function(name) => return ps.functions.get(name)?.declaration_position.clone(),
+        Symbol::Type(name) => return ps.types.get(name)?.declaration_position.clone(),
+        Symbol::Field(owner, name) => {
+            return ps
+                .types
+                .get(owner)?
+                .fields
+                .get(name)?
+                .declaration_position
+                .clone()
+        }
+        Symbol::Define(name) => return ps.defines.get(name)?.declaration_position.clone(),
+    }
+}
+
+fn is_name(node: Node) -> bool {
+    return matches!(
+        node.kind(),
+        "identifier" | "type_identifier" | "field_identifier"
+    );
+}
+
+// the struct that declares a field, for field_identifiers inside a struct body
+fn declaring_struct<'a>(src: &'a str, node: Node) -> Option<&'a str> {
+    let mut node = node.parent()?;
+    while node.kind() != "field_declaration" {
+        if !node.kind().ends_with("declarator") {
+            return None;
+        }
+        node = node.parent()?;
+    }
+    let struct_node = node.parent()?.parent()?;
+    if struct_node.kind() != "struct_specifier" {
+        return None;
+    }
+    return struct_node
+        .child_by_field_name("name")?
+        .utf8_text(src.as_bytes())
+        .ok();
+}
+
+// resolves a name node to the symbol it refers to
+pub fn resolve_node(ps: &ParseState, node: Node) -> Option<Symbol> {
+    let name = node.utf8_text(ps.text.as_bytes()).ok()?;
+
+    match node.kind() {
+        "identifier" => {
+            let is_macro_name = node.parent().is_some_and(|p| {
+                (p.kind() == "preproc_def" || p.kind() == "preproc_function_def")
+                    && p.child_by_field_name("name") == Some(node)
+            });
+            if !is_macro_name {
+                let loc = node_start(&ps.text, node);
+                if let Some(lv) = resolve_var(&ps.global_scope, name, loc) {
+                    return Some(Symbol::Var(
+                        name.to_owned(),
+                        lv.declaration_position.clone(),
+                    ));
+                }
+                if ps.functions.contains_key(name) {
+                    return Some(Symbol::Function(name.to_owned()));
+                }
+            }
+            if ps.defines.contains_key(name) {
+                return Some(Symbol::Define(name.to_owned()));
+            }
+            return None;
+        }
+        "type_identifier" => {
+            if ps.types.contains_key(name) {
+                return Some(Symbol::Type(name.to_owned()));
+            }
+            if ps.defines.contains_key(name) {
+                return Some(Symbol::Define(name.to_owned()));
+            }
+            return None;
+        }
+        "field_identifier" => {
+            let parent = node.parent()?;
+            let owner = match parent.kind() {
+                "field_expression" => field_owner(ps, parent)?,
+                _ => declaring_struct(&ps.text, node)?.to_owned(),
+            };
+            if !ps.types.get(&owner)?.fields.contains_key(name) {
+                return None;
+            }
+            return Some(Symbol::Field(owner, name.to_owned()));
+        }
+        _ => return None,
+    }
+}
+
+// the name node at (or just before) the position
+pub fn name_node_at(ps: &ParseState, position: Position) -> Option<Node<'_>> {
+    let root = ps.tree.as_ref()?.root_node();
+    let byte = position_to_byte(&ps.text, position);
+
+    let node = root.descendant_for_byte_range(byte, byte)?;
+    if is_name(node) {
+        return Some(node);
+    }
+    // the cursor is at the end of the name
+    let node = root.descendant_for_byte_range(byte.checked_sub(1)?, byte.checked_sub(1)?)?;
+    if is_name(node) {
+        return Some(node);
+    }
+    return None;
+}
+
+pub fn symbol_at(ps: &ParseState, position: Position) -> Option<(Symbol, Node<'_>)> {
+    let node = name_node_at(ps, position)?;
+    return Some((resolve_node(ps, node)?, node));
+}
+
+fn find_occurrences_recursively(
+    ps: &ParseState,
+    node: Node,
+    uri: &Url,
+    symbol: &Symbol,
+    locations: &mut Vec<Location>,
+) {
+    if is_name(node)
+        && node.utf8_text(ps.text.as_bytes()) == Ok(symbol.name())
+        && resolve_node(ps, node).as_ref() == Some(symbol)
+    {
+        locations.push(node_to_location(&ps.text, node, uri));
+    }
+    for child in node.children(&mut node.walk()) {
+        find_occurrences_recursively(ps, child, uri, symbol, locations);
+    }
+}
+
+// every place the symbol is named in the document, including its declaration
+pub fn find_occurrences(ps: &ParseState, uri: &Url, symbol: &Symbol) -> Vec<Location> {
+    let mut locations = vec![];
+    if let Some(tree) = &ps.tree {
+        find_occurrences_recursively(ps, tree.root_node(), uri, symbol, &mut locations);
+    }
+    return locations;
+}
+
+// true for vars declared in the global scope (of the document or one of its includes), which other files can name
+pub fn is_global_var(ps: &ParseState, symbol: &Symbol) -> bool {
+    let Symbol::Var(name, declaration) = symbol else {
+        return false;
+    };
+    return ps
+        .global_scope
+        .vars
+        .get(name)
+        .is_some_and(|lv| lv.declaration_position == *declaration);
+}
+
+// the global var other files know by this name, None if the file doesn't declare or include it
+// globals are matched by name across files (a definition and the `extern` declarations of it),
+// except static globals, which are private to their file
+fn global_var(ps: &ParseState, name: &str) -> Option<Symbol> {
+    let lv = ps.global_scope.vars.get(name)?;
+    if lv.type_qualifier_list.iter().any(|q| q == "static") {
+        return None;
+    }
+    return Some(Symbol::Var(
+        name.to_owned(),
+        lv.declaration_position.clone(),
+    ));
+}
+
+// the symbol as a file names it, a global var resolves to that file's own (or included) declaration
+fn symbol_in(ps: &ParseState, symbol: &Symbol) -> Option<Symbol> {
+    match symbol {
+        Symbol::Var(name, _) => return global_var(ps, name),
+        _ => return Some(symbol.clone()),
+    }
+}
+
+// occurrences of a symbol in a file that isn't open, read from disk
+// the file is merged with its includes and the rest of the workspace, as if it was open
+fn find_file_occurrences(
+    index: &mut WorkspaceIndex,
+    file_uri: &Url,
+    symbol: &Symbol,
+    profile: usize,
+    lang_db: &LangDB,
+    include_paths: &[PathBuf],
+) -> Vec<Location> {
+    let Ok(path) = file_uri.to_file_path() else {
+        return vec![];
+    };
+    let Ok(text) = fs::read_to_string(path) else {
+        return vec![];
+    };
+    if !text.contains(symbol.name()) {
+        return vec![]; // skip parsing files that can't name the symbol
+    }
+
+    let mut ps = parser::parse(text, file_uri, lang_db);
+    merge_includes(&mut ps, file_uri, index, profile, lang_db, include_paths);
+    index.merge_into(&mut ps, file_uri, profile);
+    let Some(symbol) = symbol_in(&ps, symbol) else {
+        return vec![];
+    };
+    return find_occurrences(&ps, file_uri, &symbol);
+}
+
+// occurrences of a symbol in the indexed files of the profile that aren't open, followed by the
+// files they include from inside the workspace folders (such as headers with other extensions)
+pub fn find_indexed_occurrences(
+    index: &mut WorkspaceIndex,
+    symbol: &Symbol,
+    profile: usize,
+    lang_db: &LangDB,
+    include_paths: &[PathBuf],
+    open: &HashMap<Url, ParseState>,
+    folders: &[PathBuf],
+) -> Vec<Location> {
+    let mut file_uris: Vec<Url> = index
+        .files
+        .iter()
+        .filter(|(file_uri, indexed)| indexed.profile == profile && !open.contains_key(file_uri))
+        .map(|(file_uri, _)| file_uri.to_owned())
+        .collect();
+    file_uris.sort();
+
+    let mut locations = vec![];
+    for file_uri in file_uris {
+        locations.extend(find_file_occurrences(
+            index,
+            &file_uri,
+            symbol,
+            profile,
+            lang_db,
+            include_paths,
+        ));
+    }
+
+    // every include was cached while merging the open documents and the files above
+    let mut included_uris: Vec<Url> = index
+        .included
+        .iter()
+        .filter(|(file_uri, indexed)| {
+            indexed.profile == profile
+                && !open.contains_key(file_uri)
+                && file_uri
+                    .to_file_path()
+                    .is_ok_and(|path| folders.iter().any(|folder| path.starts_with(folder)))
+        })
+        .map(|(file_uri, _)| file_uri.to_owned())
+        .collect();
+    included_uris.sort();
+    for file_uri in included_uris {
+        locations.extend(find_file_occurrences(
+            index,
+            &file_uri,
+            symbol,
+            profile,
+            lang_db,
+            include_paths,
+        ));
+    }
+    return locations;
+}
+
+impl Backend {
+    // occurrences of a symbol in every file of the profile, open documents use their unsaved text
+    // locals are only searched for in the document they are declared in
+    pub async fn find_workspace_occurrences(
+        &self,
+        uri: &Url,
+        symbol: &Symbol,
+        profiles: &[LangProfile],
+        documents: &HashMap<Url, ParseState>,
+    ) -> Vec<Location> {
+        let Some(ps) = documents.get(uri) else {
+            return vec![];
+        };
+        if let Symbol::Var(name, _) = symbol {
+            if !is_global_var(ps, symbol) || global_var(ps, name).is_none() {
+                return find_occurrences(ps, uri, symbol);
+            }
+        }
+
+        let profile = self.document_profile(uri, profiles).await;
+        let mut locations = vec![];
+        for (doc_uri, ps) in documents.iter() {
+            if self.document_profile(doc_uri, profiles).await != profile {
+                continue;
+            }
+            if let Some(doc_symbol) = symbol_in(ps, symbol) {
+                locations.extend(find_occurrences(ps, doc_uri, &doc_symbol));
+            }
+        }
+
+        let mut index = self.workspace_index.write().await;
+        locations.extend(find_indexed_occurrences(
+            &mut index,
+            symbol,
+            profile,
+            &profiles[profile].lang_db,
+            &self.include_paths.read().await,
+            documents,
+            &self.workspace_folders.read().await,
+        ));
+        return locations;
+    }
+}
@@ -0,0 +1,257 @@
+use crate::lang_types::{resolve_var, LangVar, ParseState};
+use crate::lsp_util::node_start;
+use std::fmt;
+use tree_sitter::Node;
+
+// The type of an expression, in the same form as a LangVar type
+#[derive(Debug, Clone, PartialEq)]
+pub struct InferredType {
+    pub primary_type: String,
+
+    // only the qualifiers that change the type, `[]`, `*` and `()` (for function pointers)
+    pub type_qualifier_list: Vec<String>,
+}
+
+fn is_indirection(qualifier: &str) -> bool {
+    return qualifier == "[]" || qualifier == "*" || qualifier == "()";
+}
+
+// `struct Pair` is stored as the type `Pair`
+fn type_name(primary_type: &str) -> &str {
+    for tag in ["struct ", "union ", "enum "] {
+        if let Some(name) = primary_type.strip_prefix(tag) {
+            return name.trim();
+        }
+    }
+    return primary_type;
+}
+
+impl InferredType {
+    pub fn from_var(lv: &LangVar) -> InferredType {
+        return InferredType {
+            primary_type: type_name(&lv.primary_type).to_owned(),
+            type_qualifier_list: lv
+                .type_qualifier_list
+                .iter()
+                .filter(|q| is_indirection(q))
+                .cloned()
+                .collect(),
+        };
+    }
+
+    // parses a LangDB type such as `char[]` or `int*`
+    pub fn parse(text: &str) -> InferredType {
+        let mut primary_type = text.trim();
+        let mut type_qualifier_list = vec![];
+        loop {
+            if let Some(rest) = primary_type.strip_suffix("[]") {
+                type_qualifier_list.insert(0, "[]".to_owned());
+                primary_type = rest.trim_end();
+            } else if let Some(rest) = primary_type.strip_suffix('*') {
+                type_qualifier_list.insert(0, "*".to_owned());
+                primary_type = rest.trim_end();
+            } else {
+                break;
+            }
+        }
+        return InferredType {
+            primary_type: type_name(primary_type).to_owned(),
+            type_qualifier_list,
+        };
+    }
+
+    fn indirection(&self) -> usize {
+        return self.type_qualifier_list.len();
+    }
+
+    // the type after indexing or dereferencing once
+    fn dereferenced(&self) -> Option<InferredType> {
+        let mut it = self.clone();
+        it.type_qualifier_list.pop()?;
+        return Some(it);
+    }
+}
+
+impl fmt::Display for InferredType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.primary_type)?;
+        for qualifier in self.type_qualifier_list.iter() {
+            write!(f, "{}", qualifier)?;
+        }
+        return Ok(());
+    }
+}
+
+fn converts_to(ps: &ParseState, from: &str, to: &str) -> bool {
+    return from == to
+        || ps
+            .type_rules
+            .implicit_conversions
+            .get(from)
+            .is_some_and(|targets| targets.iter().any(|t| t == to));
+}
+
+// true if a value of type `from` can be used where `to` is expected
+// arrays and pointers are interchangeable, but their element types must match (or be void)
+pub fn is_assignable(ps: &ParseState, from: &InferredType, to: &InferredType) -> bool {
+    if from.indirection() != to.indirection() {
+        return false;
+    }
+    if from.indirection() > 0 {
+        return from.primary_type == to.primary_type
+            || from.primary_type == "void"
+            || to.primary_type == "void";
+    }
+    return converts_to(ps, &from.primary_type, &to.primary_type);
+}
+
+// only types the document knows about are checked, unknown types are reported elsewhere
+// function pointers aren't checked
+pub fn is_checkable(ps: &ParseState, it: &InferredType) -> bool {
+    return ps.types.contains_key(&it.primary_type)
+        && !it.type_qualifier_list.iter().any(|q| q == "()");
+}
+
+fn rule_type(rule: &Option<String>) -> Option<InferredType> {
+    return rule.as_ref().map(|t| InferredType::parse(t));
+}
+
+// TODO lang specific - literal syntax
+fn number_literal_type(ps: &ParseState, text: &str) -> Option<InferredType> {
+    let is_hex = text.starts_with("0x") || text.starts_with("0X");
+    let is_float = text.contains('.')
+        || (!is_hex && (text.contains(['e', 'E']) || text.ends_with(['f', 'F'])));
+    match is_float {
+        true => return rule_type(&ps.type_rules.float_literal_type),
+        false => return rule_type(&ps.type_rules.int_literal_type),
+    }
+}
+
+// the result of arithmetic on two types, the type that the other one converts to
+fn promoted_type(ps: &ParseState, left: InferredType, right: InferredType) -> Option<InferredType> {
+    if left == right {
+        return Some(left);
+    }
+
+    // pointer arithmetic keeps the pointer type
+    if left.indirection() > 0 && right.indirection() == 0 {
+        return Some(left);
+    }
+    if right.indirection() > 0 && left.indirection() == 0 {
+        return Some(right);
+    }
+
+    if is_assignable(ps, &left, &right) {
+        return Some(right);
+    }
+    if is_assignable(ps, &right, &left) {
+        return Some(left);
+    }
+    return None;
+}
+
+// the struct a field expression (`a.b` or `a->b`) reads the field from
+pub fn field_owner(ps: &ParseState, node: Node) -> Option<String> {
+    let argument = infer_type(ps, node.child_by_field_name("argument")?)?;
+    let operator = node.child_by_field_name("operator")?.kind();
+
+    let base = match operator {
+        "->" => argument.dereferenced()?,
+        _ => argument,
+    };
+    if base.indirection() > 0 {
+        return None;
+    }
+    return Some(base.primary_type);
+}
+
+fn field_type(ps: &ParseState, src: &str, node: Node) -> Option<InferredType> {
+    let owner = field_owner(ps, node)?;
+    let field = node
+        .child_by_field_name("field")?
+        .utf8_text(src.as_bytes())
+        .ok()?;
+    let lv = ps.types.get(&owner)?.fields.get(field)?;
+    return Some(InferredType::from_var(lv));
+}
+
+fn cast_type(src: &str, node: Node) -> Option<InferredType> {
+    let type_descriptor = node.child_by_field_name("type")?;
+    let primary_type = type_descriptor.child_by_field_name("type")?;
+    let mut it = InferredType {
+        primary_type: type_name(primary_type.utf8_text(src.as_bytes()).ok()?).to_owned(),
+        type_qualifier_list: vec![],
+    };
+    if let Some(declarator) = type_descriptor.child_by_field_name("declarator") {
+        for c in declarator.utf8_text(src.as_bytes()).ok()?.chars() {
+            if c == '*' {
+                it.type_qualifier_list.push("*".to_owned());
+            }
+        }
+    }
+    return Some(it);
+}
+
+// works out the type of an expression, None if it can't be known
+pub fn infer_type(ps: &ParseState, node: Node) -> Option<InferredType> {
+    let src = &ps.text;
+    let text = node.utf8_text(src.as_bytes()).ok()?;
+
+    match node.kind() {
+        "identifier" => {
+            let loc = node_start(src, node);
+            let lv = resolve_var(&ps.global_scope, text, loc)?;
+            return Some(InferredType::from_var(lv));
+        }
+        "number_literal" => return number_literal_type(ps, text),
+        "char_literal" => return rule_type(&ps.type_rules.char_literal_type),
+        "string_literal" | "concatenated_string" => {
+            return rule_type(&ps.type_rules.string_literal_type)
+        }
+        "true" | "false" => return rule_type(&ps.type_rules.boolean_type),
+        "parenthesized_expression" => return infer_type(ps, node.named_child(0)?),
+        "field_expression" => return field_type(ps, src, node),
+        "subscript_expression" => {
+            return infer_type(ps, node.child_by_field_name("argument")?)?.dereferenced()
+        }
+        "call_expression" => {
+            let function = node.child_by_field_name("function")?;
+            let lf = ps.functions.get(function.utf8_text(src.as_bytes()).ok()?)?;
+            return Some(InferredType::parse(&lf.return_type));
+        }
+        "cast_expression" => return cast_type(src, node),
+        "update_expression" => return infer_type(ps, node.child_by_field_name("argument")?),
+        "assignment_expression" => return infer_type(ps, node.child_by_field_name("left")?),
+        "pointer_expression" => {
+            let argument = infer_type(ps, node.child_by_field_name("argument")?)?;
+            match node.child_by_field_name("operator")?.kind() {
+                "&" => {
+                    let mut it = argument;
+                    it.type_qualifier_list.push("*".to_owned());
+                    return Some(it);
+                }
+                _ => return argument.dereferenced(),
+            }
+        }
+        "unary_expression" => match node.child_by_field_name("operator")?.kind() {
+            "!" => return rule_type(&ps.type_rules.boolean_type),
+            _ => return infer_type(ps, node.child_by_field_name("argument")?),
+        },
+        "binary_expression" => match node.child_by_field_name("operator")?.kind() {
+            "==" | "!=" | "<" | ">" | "<=" | ">=" | "&&" | "||" => {
+                return rule_type(&ps.type_rules.boolean_type)
+            }
+            _ => {
+                let left = infer_type(ps, node.child_by_field_name("left")?)?;
+                let right = infer_type(ps, node.child_by_field_name("right")?)?;
+                return promoted_type(ps, left, right);
+            }
+        },
+        "conditional_expression" => {
+            let consequence = infer_type(ps, node.child_by_field_name("consequence")?)?;
+            let alternative = infer_type(ps, node.child_by_field_name("alternative")?)?;
+            return promoted_type(ps, consequence, alternative);
+        }
+        _ => return None,
+    }
+}
@@ -0,0 +1,361 @@
+use super::Backend;
+use crate::lang_types::*;
+use crate::lsp_util::declared_in;
+use crate::parser;
+use crate::profiles::LangProfile;
+use log::error;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use tower_lsp::lsp_types::*;
+
+const WATCHER_ID: &str = "workspace-file-watcher";
+
+// User defined objects declared in a single file
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct IndexedFile {
+    // index of the profile the file was parsed with, files only see files from the same profile
+    pub profile: usize,
+
+    pub types: HashMap<String, LangType>,
+    pub functions: HashMap<String, LangFunc>,
+    pub defines: HashMap<String, LangDefine>,
+
+    // global vars, used for workspace symbols and merged into files that include this one
+    pub globals: HashMap<String, LangVar>,
+
+    // every call expression in the file (function name, location)
+    pub calls: Vec<(String, Location)>,
+
+    // `#include` directives in the file, followed when the file is included
+    pub includes: Vec<LangInclude>,
+}
+
+// Holds the user defined objects of every file in the workspace, including files that are not open
+#[derive(Debug, Default)]
+pub struct WorkspaceIndex {
+    pub files: HashMap<Url, IndexedFile>,
+
+    // included files outside of the workspace, read from disk once and kept until invalidated
+    // not used for workspace symbols or cross-file merging
+    pub included: HashMap<Url, IndexedFile>,
+
+    // bumped whenever an indexed file changes, even if only its declarations moved
+    generation: u64,
+
+    // the generation each open document was last merged at
+    merged: HashMap<Url, u64>,
+}
+
+fn clear_var_location(lv: &mut LangVar) {
+    lv.declaration_position = None;
+    lv.unused = true;
+}
+
+impl IndexedFile {
+    // the objects declared in the (unmerged) parse state of the file at uri
+    fn new(uri: &Url, ps: &ParseState, profile: usize) -> IndexedFile {
+        let mut indexed = IndexedFile {
+            profile,
+            calls: ps.calls.clone(),
+            includes: ps.includes.clone(),
+            ..Default::default()
+        };
+
+        for (name, lt) in ps.types.iter() {
+            if declared_in(&lt.declaration_position, uri) {
+                indexed.types.insert(name.to_owned(), lt.clone());
+            }
+        }
+        for (name, lf) in ps.functions.iter() {
+            if declared_in(&lf.declaration_position, uri) {
+                let mut lf = lf.clone();
+                lf.references.clear(); // rebuilt from every file's calls in merge_into
+                indexed.functions.insert(name.to_owned(), lf);
+            }
+        }
+        for (name, ld) in ps.defines.iter() {
+            if declared_in(&ld.declaration_position, uri) {
+                indexed.defines.insert(name.to_owned(), ld.clone());
+            }
+        }
+        for (name, lv) in ps.global_scope.vars.iter() {
+            if declared_in(&lv.declaration_position, uri) {
+                indexed.globals.insert(name.to_owned(), lv.clone());
+            }
+        }
+        return indexed;
+    }
+
+    // the file with every location cleared, what's left is what other files see
+    fn without_locations(&self) -> IndexedFile {
+        let mut indexed = IndexedFile {
+            calls: vec![],
+            includes: vec![],
+            ..self.clone()
+        };
+        for lt in indexed.types.values_mut() {
+            lt.declaration_position = None;
+            lt.fields.values_mut().for_each(clear_var_location);
+        }
+        for lf in indexed.functions.values_mut() {
+            lf.declaration_position = None;
+            lf.params
+                .iter_mut()
+                .for_each(|(_, lv)| clear_var_location(lv));
+        }
+        for ld in indexed.defines.values_mut() {
+            ld.declaration_position = None;
+        }
+        indexed.globals.values_mut().for_each(clear_var_location);
+        return indexed;
+    }
+
+    // true if other files see the same names and signatures, declarations that only moved don't count
+    fn same_signatures(&self, other: &IndexedFile) -> bool {
+        let calls = |f: &IndexedFile| {
+            f.calls
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>()
+        };
+        let includes = |f: &IndexedFile| {
+            f.includes
+                .iter()
+                .map(|include| (include.path.clone(), include.system))
+                .collect::<Vec<_>>()
+        };
+        return calls(self) == calls(other)
+            && includes(self) == includes(other)
+            && self.without_locations() == other.without_locations();
+    }
+}
+
+impl WorkspaceIndex {
+    // replaces the entry for uri with the objects declared in the (unmerged) parse state
+    // returns true if names or signatures changed, so files that merge it in are outdated
+    // declarations that only moved make merged documents outdated too, but only their locations,
+    // which are refreshed when they are next needed (see is_outdated)
+    pub fn update(&mut self, uri: &Url, ps: &ParseState, profile: usize) -> bool {
+        self.invalidate(uri);
+        let indexed = IndexedFile::new(uri, ps, profile);
+        let changed = match self.files.get(uri) {
+            Some(old) if *old == indexed => return false,
+            Some(old) => !old.same_signatures(&indexed),
+            None => true,
+        };
+        self.generation += 1;
+        self.files.insert(uri.to_owned(), indexed);
+        return changed;
+    }
+
+    // records that an open document was merged with the index as it is now
+    pub fn mark_merged(&mut self, uri: &Url) {
+        self.merged.insert(uri.to_owned(), self.generation);
+    }
+
+    // forgets a document that was closed
+    pub fn forget_merged(&mut self, uri: &Url) {
+        self.merged.remove(uri);
+    }
+
+    // true if indexed files changed since the open document was merged, so its locations may be stale
+    pub fn is_outdated(&self, uri: &Url) -> bool {
+        return self
+            .merged
+            .get(uri)
+            .is_some_and(|generation| *generation != self.generation);
+    }
+
+    // true if the file is indexed or cached
+    pub fn contains(&self, path: &Path) -> bool {
+        return Url::from_file_path(path)
+            .is_ok_and(|uri| self.files.contains_key(&uri) || self.included.contains_key(&uri));
+    }
+
+    // drops the cached copy of an included file, so it is read again when it is next included
+    pub fn invalidate(&mut self, uri: &Url) {
+        self.included.remove(uri);
+    }
+
+    // the objects declared in an included file, open documents and workspace files are already
+    // indexed (with unsaved changes), other files are only read from disk when they aren't cached
+    pub fn included_file(
+        &mut self,
+        path: &Path,
+        profile: usize,
+        lang_db: &LangDB,
+    ) -> Option<&IndexedFile> {
+        let uri = Url::from_file_path(path).ok()?;
+        if self.files.contains_key(&uri) {
+            return self.files.get(&uri);
+        }
+        if !self.included.contains_key(&uri) {
+            let text = fs::read_to_string(path).ok()?;
+            let ps = parser::parse(text, &uri, lang_db);
+            self.included
+                .insert(uri.clone(), IndexedFile::new(&uri, &ps, profile));
+        }
+        return self.included.get(&uri);
+    }
+
+    // parses and indexes a file from disk, returns false if the file couldn't be read
+    pub fn index_file(&mut self, path: &Path, profile: usize, lang_db: &LangDB) -> bool {
+        let (Ok(uri), Ok(text)) = (Url::from_file_path(path), fs::read_to_string(path)) else {
+            return false;
+        };
+        self.update(&uri, &parser::parse(text, &uri, lang_db), profile);
+        return true;
+    }
+
+    // recursively indexes every file matching a profile's extensions, skipping hidden folders
+    pub fn scan_folder(&mut self, folder: &Path, profiles: &[LangProfile]) {
+        let Ok(entries) = fs::read_dir(folder) else {
+            return;
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            // symlinked folders can point back into the workspace and loop forever
+            if file_type.is_symlink() && path.is_dir() {
+                continue;
+            }
+            if file_type.is_dir() {
+                if !hidden {
+                    self.scan_folder(&path, profiles);
+                }
+            } else if let Some(idx) = profiles
+                .iter()
+                .position(|p| p.config.matches_extension(&path))
+            {
+                self.index_file(&path, idx, &profiles[idx].lang_db);
+            }
+        }
+    }
+
+    // adds objects declared in other files to the parse state, objects in the document itself take priority
+    // function references are extended with calls made from other files
+    pub fn merge_into(&self, ps: &mut ParseState, uri: &Url, profile: usize) {
+        for (file_uri, indexed) in self.files.iter() {
+            if file_uri == uri || indexed.profile != profile {
+                continue;
+            }
+
+            for (name, lt) in indexed.types.iter() {
+                if !ps.types.contains_key(name) {
+                    ps.types.insert(name.to_owned(), lt.clone());
+                }
+            }
+            for (name, lf) in indexed.functions.iter() {
+                if !ps.functions.contains_key(name) {
+                    ps.functions.insert(name.to_owned(), lf.clone());
+                }
+            }
+            for (name, ld) in indexed.defines.iter() {
+                if !ps.defines.contains_key(name) {
+                    ps.defines.insert(name.to_owned(), ld.clone());
+                }
+            }
+        }
+
+        // calls within this document were already added to its own functions by the parser
+        for (function_name, location) in ps.calls.iter() {
+            if let Some(lf) = ps.functions.get_mut(function_name) {
+                if lf.declaration_position.is_some() && !declared_in(&lf.declaration_position, uri)
+                {
+                    lf.references.push(location.clone());
+                }
+            }
+        }
+
+        for (file_uri, indexed) in self.files.iter() {
+            if file_uri == uri || indexed.profile != profile {
+                continue;
+            }
+
+            for (function_name, location) in indexed.calls.iter() {
+                if let Some(lf) = ps.functions.get_mut(function_name) {
+                    if lf.declaration_position.is_some() {
+                        lf.references.push(location.clone());
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Backend {
+    // asks the client to report changes to workspace files, so files that aren't open stay indexed
+    pub async fn register_workspace_watcher(&self) {
+        let mut watchers = vec![];
+        for profile in self.profiles.read().await.iter() {
+            for ext in profile.config.extensions.iter() {
+                watchers.push(FileSystemWatcher {
+                    glob_pattern: GlobPattern::String(format!("**/*.{}", ext)),
+                    kind: None, // create, change and delete
+                });
+            }
+        }
+        if watchers.is_empty() {
+            return;
+        }
+
+        let options = DidChangeWatchedFilesRegistrationOptions { watchers };
+        let registration = Registration {
+            id: WATCHER_ID.to_owned(),
+            method: "workspace/didChangeWatchedFiles".to_owned(),
+            register_options: Some(serde_json::to_value(options).unwrap()),
+        };
+        if let Err(e) = self.client.register_capability(vec![registration]).await {
+            error!("couldn't watch workspace files: {}", e);
+        }
+    }
+
+    // re-indexes workspace files changed outside the editor, open documents are indexed as they are edited
+    // open documents that merge in the changed files are re-merged
+    pub async fn reindex_changed_files(&self, changes: &[FileEvent]) {
+        let mut changed_profiles = vec![];
+        {
+            let profiles = self.profiles.read().await;
+            let documents = self.documents.read().await;
+            let mut index = self.workspace_index.write().await;
+
+            for change in changes {
+                // cached includes can be included by documents of any profile
+                if index.included.contains_key(&change.uri) {
+                    index.invalidate(&change.uri);
+                    changed_profiles.extend(0..profiles.len());
+                }
+                if documents.contains_key(&change.uri) {
+                    continue;
+                }
+                let Ok(path) = change.uri.to_file_path() else {
+                    continue;
+                };
+                let Some(profile) = profiles
+                    .iter()
+                    .position(|p| p.config.matches_extension(&path))
+                else {
+                    continue;
+                };
+
+                if change.typ == FileChangeType::DELETED
+                    || !index.index_file(&path, profile, &profiles[profile].lang_db)
+                {
+                    index.files.remove(&change.uri);
+                }
+                changed_profiles.push(profile);
+            }
+        }
+
+        changed_profiles.sort();
+        changed_profiles.dedup();
+        if !changed_profiles.is_empty() {
+            self.remerge_documents(&changed_profiles, None).await;
+        }
+    }
+}